@@ -0,0 +1,49 @@
+//! Measures the single thread rate of every hash backend the CPU supports.
+//! The tests in `hashing` check they all match the scalar one.
+//!
+//! Run with `cargo bench --bench backends`.
+
+use std::time::Instant;
+
+use hasher::hashing::BackendKind;
+use hasher::miner::increment_byte_string;
+use radix_fmt::radix;
+
+const STUDENT_NUMBER: &[u8] = b"12345678";
+const HASHES: u64 = 4_000_000;
+
+/// Hash `count` nonces from `nounce_start` the way the miner does, calling
+/// `each` with every nonce and its hash.
+fn run(kind: BackendKind, nounce_start: u64, count: u64, mut each: impl FnMut(&[u8], &[u8; 32])) {
+    let backend = kind.build(STUDENT_NUMBER).expect("Only supported backends are measured.");
+    let lanes = backend.lanes();
+    let mut buffer = radix(nounce_start, 36).to_string().to_uppercase().into_bytes();
+    let mut nounces = vec![vec![]; lanes];
+    let mut digests = vec![[0; 32]; lanes];
+    let mut done = 0;
+    while done < count {
+        let batch = lanes.min((count - done) as usize);
+        for nounce in nounces.iter_mut() {
+            nounce.clone_from(&buffer);
+            increment_byte_string(&mut buffer, 0);
+        }
+        backend.hash(&nounces, &mut digests);
+        for i in 0..batch {
+            each(&nounces[i], &digests[i]);
+        }
+        done += batch as u64;
+    }
+}
+
+fn main() {
+    let supported: Vec<BackendKind> = BackendKind::ALL.iter().copied().filter(BackendKind::is_supported).collect();
+
+    for &kind in supported.iter() {
+        let mut sink = 0u8;
+        let start = Instant::now();
+        run(kind, 0, HASHES, |_, hash| sink ^= hash[0]);
+        let hashes_per_second = HASHES as f64 / start.elapsed().as_secs_f64();
+        println!("{:<8} {:>8.3} MH/s (sink {})", kind.name(), hashes_per_second / 1_000_000.0, sink);
+    }
+    println!("Detected: {}", BackendKind::detect());
+}
@@ -0,0 +1,59 @@
+//! Compares hashing the whole student number and nonce buffer with `sha2` on
+//! every iteration against `NounceHasher`, which writes each nonce into a
+//! padded block template and runs the compression function once. The test
+//! next to `NounceHasher` checks both produce the same hashes.
+//!
+//! Run with `cargo bench --bench midstate`.
+
+use std::time::Instant;
+
+use hasher::miner::{increment_byte_string, NounceHasher};
+use radix_fmt::radix;
+use sha2::{Digest, Sha256};
+
+const STUDENT_NUMBER: &[u8] = b"12345678";
+const HASHES: u64 = 2_000_000;
+
+/// The hashing loop as it was before the block template was introduced.
+fn full_buffer(nounce_start: u64, count: u64, mut each: impl FnMut(&[u8], &[u8])) {
+    let mut buffer = STUDENT_NUMBER.to_vec();
+    buffer.extend(radix(nounce_start, 36).to_string().to_uppercase().bytes());
+    let sn_len = STUDENT_NUMBER.len();
+    let mut sh = Sha256::default();
+    for _ in 0..count {
+        sh.update(&buffer);
+        let sha256_buffer = sh.finalize_reset();
+        each(&buffer[sn_len..], &sha256_buffer);
+        increment_byte_string(&mut buffer, sn_len);
+    }
+}
+
+/// The hashing loop used by the miner.
+fn block_template(nounce_start: u64, count: u64, mut each: impl FnMut(&[u8], &[u8])) {
+    let hasher = NounceHasher::new(STUDENT_NUMBER);
+    let mut buffer = radix(nounce_start, 36).to_string().to_uppercase().into_bytes();
+    for _ in 0..count {
+        let sha256_buffer = hasher.hash(&buffer);
+        each(&buffer, &sha256_buffer);
+        increment_byte_string(&mut buffer, 0);
+    }
+}
+
+/// One of the hashing loops, given the first nonce, the number of nonces
+/// and a callback for each nonce and its hash.
+type HashingLoop = fn(u64, u64, &mut dyn FnMut(&[u8], &[u8]));
+
+fn time(name: &str, hashing_loop: HashingLoop) -> f64 {
+    let mut sink = 0u8;
+    let start = Instant::now();
+    hashing_loop(0, HASHES, &mut |_, hash| sink ^= hash[0]);
+    let hashes_per_second = HASHES as f64 / start.elapsed().as_secs_f64();
+    println!("{:<12} {:>8.3} MH/s (sink {})", name, hashes_per_second / 1_000_000.0, sink);
+    hashes_per_second
+}
+
+fn main() {
+    let full = time("full buffer", |start, count, each| full_buffer(start, count, each));
+    let template = time("template", |start, count, each| block_template(start, count, each));
+    println!("Speedup: {:.2}x", template / full);
+}
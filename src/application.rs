@@ -1,17 +1,41 @@
 use std::sync::Arc;
 use std::sync::Mutex;
 
-use crate::{log::Logger, net::packets::PoolStatusResponsePacket};
+use crate::{dispatcher::JobBuffer, hashing::BackendKind, log::Logger, net::{self, latency::Latencies, packets::PoolStatusResponsePacket, queue::SubmissionQueue, retry::RetryPolicy, transport::PoolTransport, NetError}};
 
 pub struct Application {
     pub student_number: String,
     pub name: String,
+    pub server_url: String,
+    /// How every thread talks to the pool, see `connect`.
+    pub transport: Arc<dyn PoolTransport>,
+    pub connection: net::ConnectSettings,
+    /// The proxy the pool is reached through, if any.
+    pub proxy: Option<net::proxy::Proxy>,
+    pub retry_policy: RetryPolicy,
+    /// Keep a push connection to the pool, see `net::push`.
+    pub push: bool,
+    /// The push connection is open, statistics and jobs need not be polled.
+    pub push_connected: bool,
     pub quitting: bool,
     pub threads_cleaned_up: bool,
     pub threads: Vec<MiningThread>,
     pub expected_thread_count: usize,
+    pub hash_backend: BackendKind,
+    /// Difficulty given on the command line, replacing the one of each job.
+    pub difficulty_override: Option<u8>,
+    /// Difficulty of the latest job.
+    pub difficulty: Option<u8>,
     pub log: Logger,
     pub pool_status: Option<PoolStatusResponsePacket>,
+    /// The latest failed request to the pool, cleared by the next request
+    /// that succeeds.
+    pub network_error: Option<NetError>,
+    pub latencies: Latencies,
+    pub submission_queue: SubmissionQueue,
+    pub job_buffer: JobBuffer,
+    pub accepted_submissions: usize,
+    pub rejected_submissions: usize,
 }
 
 
@@ -45,6 +69,7 @@ pub struct MiningThread {
     pub handle: std::thread::JoinHandle<()>,
 }
 
+#[derive(Default)]
 pub struct HashRateHistory (Vec<f64>);
 
 impl HashRateHistory {
@@ -58,7 +83,7 @@ impl HashRateHistory {
             sum += hashrate;
         }
         // Avoid divide by zero.
-        if self.0.len() == 0 {
+        if self.0.is_empty() {
             return 0.0;
         }
         sum / self.0.len() as f64
@@ -77,22 +102,70 @@ impl HashRateHistory {
 
 impl Application {
 
-    pub fn start(student_number: String, thread_count: usize, name: String) -> Self {
-        Self {
+    /// Fails when the transport for the pool cannot be set up, e.g. when the
+    /// address or the proxy is invalid.
+    pub fn start(
+        student_number: String,
+        thread_count: usize,
+        name: String,
+        server_url: String,
+        connection: &net::ConnectSettings,
+        submission_queue: SubmissionQueue,
+        hash_backend: BackendKind,
+    ) -> Result<Self, String> {
+        let mut log = Logger::new();
+        log.info(&format!("Hashing with the {} backend.", hash_backend));
+        if !submission_queue.is_empty() {
+            log.info(&format!("Loaded {} queued submission(s).", submission_queue.len()));
+        }
+        let transport = net::connect(&server_url, connection)?;
+        let proxy = net::proxy::resolve(&server_url, connection.proxy.as_deref())?;
+        if let Some(proxy) = &proxy {
+            log.info(&format!("Reaching the pool through the proxy {} from {}.", proxy, proxy.source));
+        }
+        Ok(Self {
             name,
+            transport,
+            server_url,
+            connection: connection.clone(),
+            proxy,
+            retry_policy: RetryPolicy::default(),
+            push: false,
+            push_connected: false,
             student_number,
             quitting: false,
             threads_cleaned_up: false,
             threads: vec![],
             expected_thread_count: thread_count,
-            log: Logger::new(),
+            hash_backend,
+            difficulty_override: None,
+            difficulty: None,
+            log,
             pool_status: None,
+            network_error: None,
+            latencies: Latencies::default(),
+            submission_queue,
+            job_buffer: JobBuffer::default(),
+            accepted_submissions: 0,
+            rejected_submissions: 0,
+        })
+    }
+
+    /// Talk to the pool at the given address from now on.
+    pub fn connect(&mut self, server_url: &str, settings: &net::ConnectSettings) -> Result<(), String> {
+        self.transport = net::connect(server_url, settings)?;
+        self.proxy = net::proxy::resolve(server_url, settings.proxy.as_deref())?;
+        if let Some(proxy) = &self.proxy {
+            self.log.info(&format!("Reaching the pool through the proxy {} from {}.", proxy, proxy.source));
         }
+        self.server_url = String::from(server_url);
+        self.connection = settings.clone();
+        Ok(())
     }
 
     pub fn total_hashrate(&self) -> f64 {
         // Protect against div by zero
-        if self.threads.len() == 0 {
+        if self.threads.is_empty() {
             return  0.0;
         }
         let mut sum = 0.0;
@@ -107,6 +180,7 @@ impl Application {
     }
 }
 
+#[derive(Clone)]
 pub struct App(pub Arc<Mutex<Application>>);
 
 impl App {
@@ -116,10 +190,6 @@ impl App {
 
     pub fn lock<T>(&mut self, callback: impl Fn(&mut Application) -> T) -> T {
         let mut app = self.0.lock().unwrap();
-        callback(&mut (*app))
-    }
-
-    pub fn clone(app: &Self) -> Self {
-        App (app.0.clone())
+        callback(&mut app)
     }
 }
\ No newline at end of file
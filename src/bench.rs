@@ -0,0 +1,94 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::hashing::BackendKind;
+use crate::miner::{hash_job, HashedRange};
+use crate::net::packets::{Job, DEFAULT_DIFFICULTY};
+
+/// The student number hashed by the benchmark. Any eight digits hash at the
+/// same rate.
+const STUDENT_NUMBER: &[u8] = b"00000000";
+
+/// Size of the synthetic jobs, large enough that requesting the next job
+/// does not show up in the measurement.
+const JOB_SIZE: u64 = 100_000_000;
+
+/// Adding a thread must raise the total rate by at least this fraction of a
+/// single thread's rate to count as scaling.
+const SCALING_THRESHOLD: f64 = 0.5;
+
+/// Hash rates measured by one benchmark run.
+pub struct BenchResult {
+    pub threads: usize,
+    /// Hashes per second of every thread.
+    pub per_thread: Vec<f64>,
+}
+
+impl BenchResult {
+    /// Hashes per second of all threads together.
+    pub fn total(&self) -> f64 {
+        self.per_thread.iter().sum()
+    }
+}
+
+/// Hash synthetic jobs on the given number of threads for the given
+/// duration, the same way the mining threads do but without the pool.
+pub fn run(hash_backend: BackendKind, threads: usize, duration: Duration) -> Result<BenchResult, String> {
+    if !hash_backend.is_supported() {
+        return Err(format!("The {} hash backend is not supported by this CPU.", hash_backend));
+    }
+    let deadline = Instant::now() + duration;
+    let handles: Vec<_> = (0..threads as u64)
+        .map(|id| thread::spawn(move || bench_thread(hash_backend, id, deadline)))
+        .collect();
+    let per_thread = handles
+        .into_iter()
+        .map(|handle| handle.join().expect("Benchmark thread panicked."))
+        .collect();
+    Ok(BenchResult { threads, per_thread })
+}
+
+/// Hash jobs until the deadline, returning the hash rate of the thread.
+fn bench_thread(hash_backend: BackendKind, id: u64, deadline: Instant) -> f64 {
+    let backend = hash_backend.build(STUDENT_NUMBER).expect("The hash backend was checked before the threads started.");
+    let start = Instant::now();
+    let mut hashes = 0;
+    let mut number = 0;
+    while Instant::now() < deadline {
+        // Every thread works on its own ranges, like jobs handed out by the pool.
+        let nounce_start = (id * 1_000 + number) * JOB_SIZE;
+        let job = Job {
+            number,
+            size: JOB_SIZE,
+            nounce_start,
+            nounce_end: nounce_start + JOB_SIZE,
+            difficulty: DEFAULT_DIFFICULTY,
+            offset: 0,
+            ttl: None,
+        };
+        let HashedRange { nounce_end, .. } = hash_job(
+            backend.as_ref(),
+            &job,
+            |_, _| Instant::now() < deadline,
+            |_, _| {},
+        );
+        hashes += nounce_end - job.nounce_start;
+        number += 1;
+    }
+    hashes as f64 / start.elapsed().as_secs_f64()
+}
+
+/// The thread count after which adding threads stops paying off, given
+/// results for 1, 2, 3... threads.
+pub fn scaling_limit(results: &[BenchResult]) -> usize {
+    let single = match results.first() {
+        Some(result) => result.total(),
+        None => return 0,
+    };
+    for pair in results.windows(2) {
+        if pair[1].total() - pair[0].total() < single * SCALING_THRESHOLD {
+            return pair[0].threads;
+        }
+    }
+    results.last().map(|result| result.threads).unwrap_or(0)
+}
@@ -0,0 +1,31 @@
+//! A self contained mining pool speaking the same protocol as the remote
+//! pool, for running the whole system locally. Also available as
+//! `hasher pool`.
+
+use std::process;
+use std::sync::Arc;
+
+use hasher::exit::{CliError, EXIT_CODES};
+use hasher::pool::{self, Pool, PoolArgs};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(name = "hasher-pool", after_help = EXIT_CODES)]
+struct Cli {
+    #[structopt(flatten)]
+    pool: PoolArgs,
+}
+
+fn main() {
+    let args = Cli::from_args();
+
+    if let Err(error) = serve(args.pool) {
+        eprintln!("{}", error);
+        process::exit(error.exit_code());
+    }
+}
+
+fn serve(args: PoolArgs) -> Result<(), CliError> {
+    let config = args.config().map_err(CliError::Config)?;
+    pool::http::serve(Arc::new(Pool::new(config))).map_err(CliError::Network)
+}
@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::application::{App, ThreadState};
+use crate::net::{self, packets::{Job, SubmittionPacket}, retry};
+
+/// Upper bound of the number of jobs fetched ahead of time.
+const MAX_BUFFERED_JOBS: usize = 4;
+
+/// Jobs fetched from the pool ahead of time, so a mining thread can start on
+/// its next range the moment it finishes the current one.
+#[derive(Default)]
+pub struct JobBuffer {
+    jobs: VecDeque<BufferedJob>,
+    /// Time the mining threads spent waiting for a job, all threads together.
+    pub waiting: Duration,
+}
+
+struct BufferedJob {
+    job: Job,
+    received: Instant,
+}
+
+impl BufferedJob {
+    /// A job with less than half of its time left is as good as stale, a
+    /// thread might not finish it before the pool gives the range away.
+    fn is_stale(&self) -> bool {
+        match self.job.ttl {
+            Some(ttl) => self.received.elapsed() * 2 >= Duration::from_secs(ttl),
+            None => false,
+        }
+    }
+}
+
+impl JobBuffer {
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// The number of jobs to keep buffered for the given number of threads.
+    pub fn depth(thread_count: usize) -> usize {
+        thread_count.min(MAX_BUFFERED_JOBS)
+    }
+
+    pub fn push(&mut self, job: Job) {
+        self.jobs.push_back(BufferedJob { job, received: Instant::now() });
+    }
+
+    /// Remove the job with the given number. Returns whether it was
+    /// buffered.
+    pub fn remove(&mut self, job_number: u64) -> bool {
+        let before = self.jobs.len();
+        self.jobs.retain(|buffered| buffered.job.number != job_number);
+        before != self.jobs.len()
+    }
+
+    /// Take the oldest job that is not stale.
+    fn pop(&mut self) -> Option<Job> {
+        self.discard_stale();
+        self.jobs.pop_front().map(|buffered| buffered.job)
+    }
+
+    /// Throw away the jobs the pool considers stale. Returns how many were
+    /// dropped.
+    fn discard_stale(&mut self) -> usize {
+        let before = self.jobs.len();
+        self.jobs.retain(|buffered| !buffered.is_stale());
+        before - self.jobs.len()
+    }
+
+    /// Remove every job from the buffer.
+    fn drain(&mut self) -> Vec<Job> {
+        self.jobs.drain(..).map(|buffered| buffered.job).collect()
+    }
+}
+
+/// Take the next job for a mining thread, waiting for the dispatcher when
+/// the buffer is empty. Returns `None` once the thread is told to stop.
+pub fn take_job(mut app: App, state: &Arc<Mutex<ThreadState>>) -> Option<Job> {
+    let start = Instant::now();
+    loop {
+        if *state.lock().unwrap() == ThreadState::StopSignal {
+            return None;
+        }
+        let job = app.lock(|app| {
+            let job = app.job_buffer.pop();
+            if job.is_some() {
+                app.job_buffer.waiting += start.elapsed();
+            }
+            job
+        });
+        if job.is_some() {
+            return job;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Start the thread that keeps the job buffer filled. When the application
+/// quits, the jobs nobody started are handed back to the pool.
+pub fn begin(mut app: App) -> std::thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let quitting = net::until_quitting(&app);
+        // Requests that failed in a row, for the backoff between them.
+        let mut failures = 0;
+        while !quitting() {
+            let discarded = app.lock(|app| app.job_buffer.discard_stale());
+            if discarded > 0 {
+                app.lock(|app| app.log.info(&format!("Discarded {} stale job(s).", discarded)));
+            }
+
+            // Over the push connection the pool sends the next job with
+            // each submission, fetch jobs only to never leave a thread
+            // waiting.
+            let wanted = app.lock(|app| if app.push_connected {
+                app.job_buffer.is_empty()
+            } else {
+                JobBuffer::depth(app.expected_thread_count) > app.job_buffer.len()
+            });
+            if !wanted {
+                thread::sleep(Duration::from_millis(20));
+                continue;
+            }
+            match net::request_job(App::clone(&app), &quitting) {
+                Ok(job) => {
+                    failures = 0;
+                    app.lock(|app| app.job_buffer.push(job));
+                }
+                Err(_) => {
+                    // Error reported to log screen by request_job(...). Back
+                    // off from the base delay, randomised so clients do not
+                    // all return at once.
+                    failures += 1;
+                    let delay = app.lock(|app| app.retry_policy.delay(failures));
+                    app.lock(|app| app.log.error(&format!(
+                        "Cannot aquire job. Waiting {:.1} seconds...",
+                        delay.as_secs_f64(),
+                    )));
+                    retry::sleep_unless_stopped(delay, &quitting);
+                }
+            }
+        }
+
+        for job in app.lock(|app| app.job_buffer.drain()) {
+            release_job(App::clone(&app), job);
+        }
+    })
+}
+
+/// Hand an unstarted job back to the pool as an empty partial submission,
+/// so the pool can give the range to someone else straight away.
+fn release_job(mut app: App, job: Job) {
+    let packet = app.lock(|app| SubmittionPacket {
+        job_n: job.number,
+        name: app.name.clone(),
+        student_number: app.student_number.clone(),
+        thread_hashes_per_second: 0.0,
+        total_hashes_per_second: 0.0,
+        nounce_start: job.nounce_start,
+        nounce_end: job.nounce_start + job.offset,
+        solutions: vec![],
+        offset: job.offset,
+        partial: true,
+        explain_rejections: true,
+    });
+    // Already quitting, a single attempt is enough. The pool hands the
+    // range out again once the job goes stale anyway.
+    let _ = net::submit_job(app, &packet, &|| true);
+}
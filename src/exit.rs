@@ -0,0 +1,87 @@
+//! Why a command of `hasher` or `hasher-pool` failed, and the exit code of
+//! each reason. The codes start at 10, clear of the 1 and 2 used by the
+//! argument parser for usage errors and of 101 for a panic.
+
+use std::error::Error;
+use std::fmt;
+
+/// The exit codes, for the `--help` text of both programs.
+pub const EXIT_CODES: &str = "EXIT CODES:
+    10    Some solutions in the file given to `verify` are invalid
+    11    The student number or machine name is missing or malformed
+    12    Invalid configuration file, environment variable or flag
+    13    The pool could not be reached, or the bundled pool could not listen
+    14    The terminal interface failed
+    15    Reading or writing a local file failed";
+
+/// Why a command failed. Each kind exits with its own code, so scripts can
+/// tell them apart.
+#[derive(Debug)]
+pub enum CliError {
+    /// Some solutions in the file given to `verify` are invalid.
+    InvalidSolutions { invalid: usize, total: usize },
+    /// The student number or machine name is missing or malformed.
+    InvalidIdentity(String),
+    /// A configuration file, environment variable or flag holds an invalid
+    /// value.
+    Config(String),
+    /// The pool could not be reached while starting, or the bundled pool
+    /// could not listen.
+    Network(String),
+    /// The terminal interface could not be set up or drawn.
+    Terminal(String),
+    /// Reading or writing a local file failed.
+    Io(String),
+}
+
+impl CliError {
+    /// The code to exit with, as listed in `EXIT_CODES`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::InvalidSolutions { .. } => 10,
+            CliError::InvalidIdentity(_) => 11,
+            CliError::Config(_) => 12,
+            CliError::Network(_) => 13,
+            CliError::Terminal(_) => 14,
+            CliError::Io(_) => 15,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::InvalidSolutions { invalid, total } => {
+                write!(f, "{} of {} solutions are invalid.", invalid, total)
+            }
+            CliError::InvalidIdentity(message) => write!(f, "Invalid identity: {}", message),
+            CliError::Config(message) => write!(f, "Invalid configuration: {}", message),
+            CliError::Network(message) => write!(f, "Network failure: {}", message),
+            CliError::Terminal(message) => write!(f, "Terminal failure: {}", message),
+            CliError::Io(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for CliError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_are_listed_and_clear_of_the_parser() {
+        let errors = [
+            CliError::InvalidSolutions { invalid: 1, total: 2 },
+            CliError::InvalidIdentity(String::new()),
+            CliError::Config(String::new()),
+            CliError::Network(String::new()),
+            CliError::Terminal(String::new()),
+            CliError::Io(String::new()),
+        ];
+        for error in errors.iter() {
+            assert!(error.exit_code() >= 10, "{:?}", error);
+            assert!(EXIT_CODES.contains(&format!("\n    {}    ", error.exit_code())), "{:?}", error);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod simd;
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use sha2::digest::generic_array::GenericArray;
+
+use crate::miner::NounceHasher;
+
+/// How long each backend hashes when `BackendKind::detect` compares them.
+const DETECT_TIME: Duration = Duration::from_millis(50);
+
+/// Initial SHA-256 state.
+pub(crate) const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+    0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// SHA-256 round constants.
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Computes the hashes of the student number followed by a batch of nonces.
+pub trait HashBackend: Send {
+    /// Number of nonces hashed by each call to `hash`.
+    fn lanes(&self) -> usize;
+
+    /// Hash the student number followed by each nonce. Both slices hold
+    /// `lanes()` entries.
+    fn hash(&self, nounces: &[Vec<u8>], digests: &mut [[u8; 32]]);
+}
+
+/// The available hashing implementations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    /// One nonce at a time through `sha2`, the portable fallback.
+    Scalar,
+    /// The padded block of each nonce through the `sha2` compression
+    /// function, four nonces per call.
+    Blocks,
+    /// Four nonces in parallel using 128 bit vectors.
+    Sse41,
+    /// Eight nonces in parallel using 256 bit vectors.
+    Avx2,
+}
+
+impl BackendKind {
+    pub const ALL: [BackendKind; 4] = [
+        BackendKind::Scalar,
+        BackendKind::Blocks,
+        BackendKind::Sse41,
+        BackendKind::Avx2,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackendKind::Scalar => "scalar",
+            BackendKind::Blocks => "blocks",
+            BackendKind::Sse41 => "sse4.1",
+            BackendKind::Avx2 => "avx2",
+        }
+    }
+
+    /// Whether the CPU running the program can use this backend.
+    pub fn is_supported(&self) -> bool {
+        match self {
+            BackendKind::Scalar | BackendKind::Blocks => true,
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            BackendKind::Sse41 => is_x86_feature_detected!("sse4.1"),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            BackendKind::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+            _ => false,
+        }
+    }
+
+    /// The backend hashing fastest on the CPU running the program. Wider
+    /// vectors are not always faster, so each supported backend hashes for
+    /// `DETECT_TIME` and the best rate wins.
+    pub fn detect() -> Self {
+        let mut fastest = (BackendKind::Scalar, 0.0);
+        for kind in BackendKind::ALL.iter().copied().filter(BackendKind::is_supported) {
+            let rate = kind.measure();
+            if rate > fastest.1 {
+                fastest = (kind, rate);
+            }
+        }
+        fastest.0
+    }
+
+    /// Nonces hashed per second by one thread, for a student number and
+    /// nonce of typical length.
+    fn measure(&self) -> f64 {
+        let backend = match self.build(b"12345678") {
+            Some(backend) => backend,
+            None => return 0.0,
+        };
+        let lanes = backend.lanes();
+        let nounces = vec![b"0000001".to_vec(); lanes];
+        let mut digests = vec![[0; 32]; lanes];
+        let start = Instant::now();
+        let mut hashes = 0;
+        while start.elapsed() < DETECT_TIME {
+            backend.hash(&nounces, &mut digests);
+            hashes += lanes;
+        }
+        hashes as f64 / start.elapsed().as_secs_f64()
+    }
+
+    /// Create the backend for the given student number, or `None` when the
+    /// CPU does not support it. The vector backends rely on this check.
+    pub fn build(&self, student_number: &[u8]) -> Option<Box<dyn HashBackend>> {
+        if !self.is_supported() {
+            return None;
+        }
+        let prefix = student_number.to_vec();
+        match self {
+            BackendKind::Scalar => Some(Box::new(Scalar(NounceHasher::new(student_number)))),
+            BackendKind::Blocks => Some(Box::new(Blocks { prefix })),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            BackendKind::Sse41 => Some(Box::new(simd::Sse41 { prefix })),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            BackendKind::Avx2 => Some(Box::new(simd::Avx2 { prefix })),
+            #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        BackendKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = BackendKind::ALL.iter().map(BackendKind::name).collect();
+                format!("Unknown hash backend \"{}\", expected one of: {}.", name, names.join(", "))
+            })
+    }
+}
+
+/// Today's hashing loop, one nonce per call.
+struct Scalar(NounceHasher);
+
+impl HashBackend for Scalar {
+    fn lanes(&self) -> usize {
+        1
+    }
+
+    fn hash(&self, nounces: &[Vec<u8>], digests: &mut [[u8; 32]]) {
+        digests[0] = self.0.hash(&nounces[0]);
+    }
+}
+
+/// Builds the single padded block of each message and runs the `sha2`
+/// compression function on it.
+struct Blocks {
+    prefix: Vec<u8>,
+}
+
+impl HashBackend for Blocks {
+    fn lanes(&self) -> usize {
+        4
+    }
+
+    fn hash(&self, nounces: &[Vec<u8>], digests: &mut [[u8; 32]]) {
+        for (nounce, digest) in nounces.iter().zip(digests.iter_mut()) {
+            let mut block = [0; 64];
+            if !pad_block(&self.prefix, nounce, &mut block) {
+                *digest = hash_long(&self.prefix, nounce);
+                continue;
+            }
+            let mut state = H0;
+            sha2::compress256(&mut state, std::slice::from_ref(GenericArray::from_slice(&block)));
+            *digest = state_to_digest(&state);
+        }
+    }
+}
+
+/// Write the padded message into a single block. Returns false when the
+/// message is too long to fit in one block.
+fn pad_block(prefix: &[u8], nounce: &[u8], block: &mut [u8; 64]) -> bool {
+    let length = prefix.len() + nounce.len();
+    if length > 55 {
+        return false;
+    }
+    block[..prefix.len()].copy_from_slice(prefix);
+    block[prefix.len()..length].copy_from_slice(nounce);
+    block[length] = 0x80;
+    for byte in block[length + 1..56].iter_mut() {
+        *byte = 0;
+    }
+    block[56..].copy_from_slice(&((length as u64) * 8).to_be_bytes());
+    true
+}
+
+/// The message words of the padded single block message.
+fn block_words(prefix: &[u8], nounce: &[u8]) -> Option<[u32; 16]> {
+    let mut block = [0; 64];
+    if !pad_block(prefix, nounce, &mut block) {
+        return None;
+    }
+    let mut words = [0; 16];
+    for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    Some(words)
+}
+
+pub(crate) fn state_to_digest(state: &[u32; 8]) -> [u8; 32] {
+    let mut digest = [0; 32];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// Messages longer than one block take the regular path.
+fn hash_long(prefix: &[u8], nounce: &[u8]) -> [u8; 32] {
+    NounceHasher::new(prefix).hash(nounce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miner::increment_byte_string;
+    use radix_fmt::radix;
+
+    /// Hash `count` nonces from `nounce_start` the way the miner does.
+    fn hashes(kind: BackendKind, prefix: &[u8], nounce_start: u64, count: usize) -> Vec<(Vec<u8>, [u8; 32])> {
+        let backend = kind.build(prefix).unwrap();
+        let lanes = backend.lanes();
+        let mut buffer = radix(nounce_start, 36).to_string().to_uppercase().into_bytes();
+        let mut nounces = vec![vec![]; lanes];
+        let mut digests = vec![[0; 32]; lanes];
+        let mut hashes = vec![];
+        while hashes.len() < count {
+            for nounce in nounces.iter_mut() {
+                nounce.clone_from(&buffer);
+                increment_byte_string(&mut buffer, 0);
+            }
+            backend.hash(&nounces, &mut digests);
+            hashes.extend(nounces.iter().cloned().zip(digests.iter().copied()));
+        }
+        hashes.truncate(count);
+        hashes
+    }
+
+    #[test]
+    fn supported_backends_match_scalar() {
+        // Single digit to four digits, a four digit nonce rolling over to
+        // five, and messages growing past a single block.
+        let cases: [(&[u8], u64); 3] = [
+            (b"12345678", 0),
+            (b"12345678", u64::from_str_radix("YZZZ", 36).unwrap()),
+            (&[b'7'; 52], 0),
+        ];
+        for &(prefix, nounce_start) in cases.iter() {
+            let expected = hashes(BackendKind::Scalar, prefix, nounce_start, 50_000);
+            for kind in BackendKind::ALL.iter().copied().filter(BackendKind::is_supported) {
+                for (i, (nounce, digest)) in hashes(kind, prefix, nounce_start, 50_000).into_iter().enumerate() {
+                    assert_eq!(expected[i].0, nounce, "{} skipped a nonce", kind);
+                    assert_eq!(expected[i].1, digest, "{} differs on nonce {:?}", kind, String::from_utf8_lossy(&nounce));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn only_supported_backends_are_built() {
+        for kind in BackendKind::ALL.iter() {
+            assert_eq!(kind.build(b"12345678").is_some(), kind.is_supported(), "{}", kind);
+        }
+    }
+}
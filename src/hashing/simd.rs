@@ -0,0 +1,213 @@
+//! Multi-buffer SHA-256. Each vector lane holds one message, so a 128 bit
+//! vector hashes four nonces at once and a 256 bit vector eight.
+
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use super::{block_words, hash_long, HashBackend, H0, K};
+
+/// Rotate every lane right by `$n` bits, `$m` is `32 - $n`.
+macro_rules! rotr {
+    ($ops:ident, $x:expr, $n:literal, $m:literal) => {
+        $ops!(or, $ops!(srli, $x, $n), $ops!(slli, $x, $m))
+    };
+}
+
+/// Compress one padded block per lane, starting from the initial state.
+/// `$ops` maps the operation names onto the intrinsics of a vector width.
+macro_rules! compress_lanes {
+    ($ops:ident, $words:expr) => {{
+        let mut w = [$ops!(splat, 0); 64];
+        w[..16].copy_from_slice(&$words);
+        for t in 16..64 {
+            let s0 = $ops!(xor,
+                $ops!(xor, rotr!($ops, w[t - 15], 7, 25), rotr!($ops, w[t - 15], 18, 14)),
+                $ops!(srli, w[t - 15], 3));
+            let s1 = $ops!(xor,
+                $ops!(xor, rotr!($ops, w[t - 2], 17, 15), rotr!($ops, w[t - 2], 19, 13)),
+                $ops!(srli, w[t - 2], 10));
+            w[t] = $ops!(add, $ops!(add, w[t - 16], s0), $ops!(add, w[t - 7], s1));
+        }
+
+        let mut a = $ops!(splat, H0[0]);
+        let mut b = $ops!(splat, H0[1]);
+        let mut c = $ops!(splat, H0[2]);
+        let mut d = $ops!(splat, H0[3]);
+        let mut e = $ops!(splat, H0[4]);
+        let mut f = $ops!(splat, H0[5]);
+        let mut g = $ops!(splat, H0[6]);
+        let mut h = $ops!(splat, H0[7]);
+        for t in 0..64 {
+            let sigma1 = $ops!(xor,
+                $ops!(xor, rotr!($ops, e, 6, 26), rotr!($ops, e, 11, 21)),
+                rotr!($ops, e, 25, 7));
+            let ch = $ops!(xor, $ops!(and, e, f), $ops!(andnot, e, g));
+            let t1 = $ops!(add,
+                $ops!(add, h, sigma1),
+                $ops!(add, ch, $ops!(add, $ops!(splat, K[t]), w[t])));
+            let sigma0 = $ops!(xor,
+                $ops!(xor, rotr!($ops, a, 2, 30), rotr!($ops, a, 13, 19)),
+                rotr!($ops, a, 22, 10));
+            let maj = $ops!(xor, $ops!(xor, $ops!(and, a, b), $ops!(and, a, c)), $ops!(and, b, c));
+            let t2 = $ops!(add, sigma0, maj);
+            h = g;
+            g = f;
+            f = e;
+            e = $ops!(add, d, t1);
+            d = c;
+            c = b;
+            b = a;
+            a = $ops!(add, t1, t2);
+        }
+
+        [
+            $ops!(add, a, $ops!(splat, H0[0])),
+            $ops!(add, b, $ops!(splat, H0[1])),
+            $ops!(add, c, $ops!(splat, H0[2])),
+            $ops!(add, d, $ops!(splat, H0[3])),
+            $ops!(add, e, $ops!(splat, H0[4])),
+            $ops!(add, f, $ops!(splat, H0[5])),
+            $ops!(add, g, $ops!(splat, H0[6])),
+            $ops!(add, h, $ops!(splat, H0[7])),
+        ]
+    }};
+}
+
+macro_rules! sse {
+    (splat, $x:expr) => { _mm_set1_epi32($x as i32) };
+    (add, $x:expr, $y:expr) => { _mm_add_epi32($x, $y) };
+    (xor, $x:expr, $y:expr) => { _mm_xor_si128($x, $y) };
+    (and, $x:expr, $y:expr) => { _mm_and_si128($x, $y) };
+    (andnot, $x:expr, $y:expr) => { _mm_andnot_si128($x, $y) };
+    (or, $x:expr, $y:expr) => { _mm_or_si128($x, $y) };
+    (srli, $x:expr, $n:literal) => { _mm_srli_epi32::<$n>($x) };
+    (slli, $x:expr, $n:literal) => { _mm_slli_epi32::<$n>($x) };
+}
+
+macro_rules! avx2 {
+    (splat, $x:expr) => { _mm256_set1_epi32($x as i32) };
+    (add, $x:expr, $y:expr) => { _mm256_add_epi32($x, $y) };
+    (xor, $x:expr, $y:expr) => { _mm256_xor_si256($x, $y) };
+    (and, $x:expr, $y:expr) => { _mm256_and_si256($x, $y) };
+    (andnot, $x:expr, $y:expr) => { _mm256_andnot_si256($x, $y) };
+    (or, $x:expr, $y:expr) => { _mm256_or_si256($x, $y) };
+    (srli, $x:expr, $n:literal) => { _mm256_srli_epi32::<$n>($x) };
+    (slli, $x:expr, $n:literal) => { _mm256_slli_epi32::<$n>($x) };
+}
+
+#[target_feature(enable = "sse4.1")]
+unsafe fn compress4(blocks: &[[u32; 16]; 4]) -> [[u32; 8]; 4] {
+    let mut words = [_mm_setzero_si128(); 16];
+    for (i, word) in words.iter_mut().enumerate() {
+        *word = _mm_set_epi32(
+            blocks[3][i] as i32,
+            blocks[2][i] as i32,
+            blocks[1][i] as i32,
+            blocks[0][i] as i32,
+        );
+    }
+    let state: [__m128i; 8] = compress_lanes!(sse, words);
+
+    let mut states = [[0; 8]; 4];
+    for (i, word) in state.iter().enumerate() {
+        let mut lanes = [0u32; 4];
+        _mm_storeu_si128(lanes.as_mut_ptr() as *mut __m128i, *word);
+        for (lane, value) in lanes.iter().enumerate() {
+            states[lane][i] = *value;
+        }
+    }
+    states
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn compress8(blocks: &[[u32; 16]; 8]) -> [[u32; 8]; 8] {
+    let mut words = [_mm256_setzero_si256(); 16];
+    for (i, word) in words.iter_mut().enumerate() {
+        *word = _mm256_set_epi32(
+            blocks[7][i] as i32,
+            blocks[6][i] as i32,
+            blocks[5][i] as i32,
+            blocks[4][i] as i32,
+            blocks[3][i] as i32,
+            blocks[2][i] as i32,
+            blocks[1][i] as i32,
+            blocks[0][i] as i32,
+        );
+    }
+    let state: [__m256i; 8] = compress_lanes!(avx2, words);
+
+    let mut states = [[0; 8]; 8];
+    for (i, word) in state.iter().enumerate() {
+        let mut lanes = [0u32; 8];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, *word);
+        for (lane, value) in lanes.iter().enumerate() {
+            states[lane][i] = *value;
+        }
+    }
+    states
+}
+
+/// Lay out the message words of every lane. Returns false if any message
+/// does not fit in a single block.
+fn lane_words(prefix: &[u8], nounces: &[Vec<u8>], blocks: &mut [[u32; 16]]) -> bool {
+    for (nounce, block) in nounces.iter().zip(blocks.iter_mut()) {
+        match block_words(prefix, nounce) {
+            Some(words) => *block = words,
+            None => return false,
+        }
+    }
+    true
+}
+
+pub struct Sse41 {
+    pub prefix: Vec<u8>,
+}
+
+impl HashBackend for Sse41 {
+    fn lanes(&self) -> usize {
+        4
+    }
+
+    fn hash(&self, nounces: &[Vec<u8>], digests: &mut [[u8; 32]]) {
+        let mut blocks = [[0; 16]; 4];
+        if !lane_words(&self.prefix, nounces, &mut blocks) {
+            for (nounce, digest) in nounces.iter().zip(digests.iter_mut()) {
+                *digest = hash_long(&self.prefix, nounce);
+            }
+            return;
+        }
+        // Safety: `BackendKind::build` only builds it when SSE4.1 was detected.
+        let states = unsafe { compress4(&blocks) };
+        for (state, digest) in states.iter().zip(digests.iter_mut()) {
+            *digest = super::state_to_digest(state);
+        }
+    }
+}
+
+pub struct Avx2 {
+    pub prefix: Vec<u8>,
+}
+
+impl HashBackend for Avx2 {
+    fn lanes(&self) -> usize {
+        8
+    }
+
+    fn hash(&self, nounces: &[Vec<u8>], digests: &mut [[u8; 32]]) {
+        let mut blocks = [[0; 16]; 8];
+        if !lane_words(&self.prefix, nounces, &mut blocks) {
+            for (nounce, digest) in nounces.iter().zip(digests.iter_mut()) {
+                *digest = hash_long(&self.prefix, nounce);
+            }
+            return;
+        }
+        // Safety: `BackendKind::build` only builds it when AVX2 was detected.
+        let states = unsafe { compress8(&blocks) };
+        for (state, digest) in states.iter().zip(digests.iter_mut()) {
+            *digest = super::state_to_digest(state);
+        }
+    }
+}
+
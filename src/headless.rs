@@ -0,0 +1,42 @@
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::application::Application;
+use crate::log::LogMessage;
+
+/// Run without the terminal interface, for services, containers and pipes.
+/// Log messages are written as plain lines, see `print_log`. Runs until the
+/// application quits, e.g. on SIGINT or SIGTERM, and the mining threads
+/// have finished. Returns the number of log messages printed so far.
+pub fn main_loop(app: Arc<Mutex<Application>>) -> Result<usize, Box<dyn Error>> {
+    let mut written = 0;
+    loop {
+        let done = {
+            let app = app.lock().unwrap();
+            app.quitting && app.threads_cleaned_up
+        };
+        written = print_log(&app, written);
+        if done {
+            return Ok(written);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Print the messages logged after the first `written` ones, errors to
+/// stderr and everything else to stdout. Returns the number of messages
+/// logged so far.
+pub fn print_log(app: &Arc<Mutex<Application>>, written: usize) -> usize {
+    let (messages, written) = {
+        let app = app.lock().unwrap();
+        (app.log.since(written).to_vec(), app.log.written())
+    };
+    for message in messages.iter() {
+        match message {
+            LogMessage::Error(_) => eprintln!("{}", message),
+            _ => println!("{}", message),
+        }
+    }
+    written
+}
@@ -0,0 +1,21 @@
+//! The hasher mining client and the pieces shared with the bundled pool
+//! server, most importantly the wire format in `net::packets`.
+
+pub mod application;
+pub mod bench;
+pub mod config;
+pub mod dispatcher;
+pub mod exit;
+pub mod hashing;
+pub mod headless;
+pub mod miner;
+pub mod ui;
+pub mod log;
+pub mod net;
+pub mod pool;
+pub mod signals;
+pub mod status;
+pub mod verify;
+
+#[allow(dead_code)]
+mod util;
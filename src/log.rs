@@ -1,3 +1,7 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
 
 #[derive(Clone)]
 pub enum LogMessage {
@@ -5,11 +9,31 @@ pub enum LogMessage {
     Info(String),
     Error(String),
 }
-pub struct Logger (Vec<LogMessage>);
+impl fmt::Display for LogMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogMessage::Solution { hash, nounce, leading_zero_bit_length } => {
+                write!(f, "[ OK ]  {:<5}{}   {}", leading_zero_bit_length, hash, nounce)
+            }
+            LogMessage::Error(message) => write!(f, "[ ERR ] {}", message),
+            LogMessage::Info(message) => write!(f, "[ INF ] {}", message),
+        }
+    }
+}
+
+/// The latest log messages, oldest first.
+#[derive(Default)]
+pub struct Logger {
+    messages: Vec<LogMessage>,
+    /// Number of messages logged since the start, including dropped ones.
+    written: usize,
+    /// Every message is appended to this file as well, see `open_file`.
+    file: Option<File>,
+}
 
 impl Logger {
     pub fn new() -> Self {
-        Self(Vec::new())
+        Self::default()
     }
 
     pub fn solution(&mut self, hash: &String, nounce: &String, leading_zero_bit_length: u8) {
@@ -18,7 +42,7 @@ impl Logger {
 
         self.clear_if_too_large();
 
-        self.0.push(LogMessage::Solution{
+        self.push(LogMessage::Solution{
             hash,
             nounce,
             leading_zero_bit_length,
@@ -27,7 +51,7 @@ impl Logger {
 
     pub fn error(&mut self, message: &str) {
         self.clear_if_too_large();
-        self.0.push(LogMessage::Error(
+        self.push(LogMessage::Error(
             String::from(message)
         ));
     }
@@ -35,21 +59,58 @@ impl Logger {
 
     pub fn info(&mut self, message: &str) {
         self.clear_if_too_large();
-        self.0.push(LogMessage::Info(
+        self.push(LogMessage::Info(
             String::from(message)
         ));
     }
 
     pub fn len(&mut self) -> usize {
-        self.0.len()
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
     }
 
     pub fn pop(&mut self) -> LogMessage {
-        self.0.remove(0)
+        self.messages.remove(0)
     }
 
     pub fn get(&self) -> &Vec<LogMessage> {
-        &self.0
+        &self.messages
+    }
+
+    /// Number of messages logged since the start.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// The messages logged after the first `written` ones that are still
+    /// kept.
+    pub fn since(&self, written: usize) -> &[LogMessage] {
+        let new = self.written.saturating_sub(written).min(self.messages.len());
+        &self.messages[self.messages.len() - new..]
+    }
+
+    /// Append every message to the file from now on, starting with the
+    /// ones still kept.
+    pub fn open_file(&mut self, path: &Path) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        for message in self.messages.iter() {
+            writeln!(file, "{}", message)?;
+        }
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn push(&mut self, message: LogMessage) {
+        if let Some(file) = self.file.as_mut() {
+            // Nowhere left to report a failed write, keep the message on
+            // screen at least.
+            let _ = writeln!(file, "{}", message);
+        }
+        self.messages.push(message);
+        self.written += 1;
     }
 
     fn clear_if_too_large(&mut self) {
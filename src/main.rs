@@ -1,95 +1,542 @@
-///         ______                     
-///         _________        .---"""      """---.              
-///         :______.-':      :  .--------------.  :             
-///         | ______  |      | :                : |             
-///         |:______B:|      | |  Hasher 0.2    | |             
-///         |:______B:|      | |                | |             
-///         |:______B:|      | |  #########>  | | |             
-///         |         |      | |  ##########> | | |             
-///         |:_____:  |      | |  ########>   | | |             
-///         |    ==   |      | :                : |             
-///         |       O |      :  '--------------'  :             
-///         |       o |      :'---...______...---'              
-///         |       o |-._.-i___/'             \._              
-///         |'-.____o_|   '-.   '-...______...-'  `-._          
-///         :_________:      `.____________________   `-.___.-. 
-///                         .'.eeeeeeeeeeeeeeeeee.'.      :___:
-///                       .'.eeeeeeeeeeeeeeeeeeeeee.'.         
-///                      :____________________________:
-
-mod application;
-mod miner;
-mod ui;
-mod log;
-mod net;
-mod status;
-
-#[allow(dead_code)]
-mod util;
-
-use std::error::Error;
+//!         ______                     
+//!         _________        .---"""      """---.              
+//!         :______.-':      :  .--------------.  :             
+//!         | ______  |      | :                : |             
+//!         |:______B:|      | |  Hasher 0.2    | |             
+//!         |:______B:|      | |                | |             
+//!         |:______B:|      | |  #########>  | | |             
+//!         |         |      | |  ##########> | | |             
+//!         |:_____:  |      | |  ########>   | | |             
+//!         |    ==   |      | :                : |             
+//!         |       O |      :  '--------------'  :             
+//!         |       o |      :'---...______...---'              
+//!         |       o |-._.-i___/'             \._              
+//!         |'-.____o_|   '-.   '-...______...-'  `-._          
+//!         :_________:      `.____________________   `-.___.-. 
+//!                         .'.eeeeeeeeeeeeeeeeee.'.      :___:
+//!                       .'.eeeeeeeeeeeeeeeeeeeeee.'.         
+//!                      :____________________________:
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::process;
 use std::sync::Arc;
 use std::sync::Mutex;
-use application::App;
-use num_cpus;
+use std::time::{Duration, Instant};
+use hasher::{application, bench, config, dispatcher, headless, miner, net, pool, signals, status, ui, verify};
+use hasher::application::App;
+use hasher::exit::{CliError, EXIT_CODES};
+use hasher::hashing::BackendKind;
+use hasher::net::packets::{PoolStatusRequestPacket, Solution};
+use hasher::net::queue::SubmissionQueue;
 use structopt::StructOpt;
 
+/// Mine SHA-256 shares for a pool.
 #[derive(StructOpt)]
+#[structopt(name = "hasher", after_help = EXIT_CODES)]
 struct Cli {
-    student_number: String,
-    machine_name: Option<String>,
-    thread_count: Option<usize>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+    #[structopt(flatten)]
+    mine: MineArgs,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-
-    let args = Cli::from_args();
+// Settings of a mining run, also accepted without the `mine` subcommand. A
+// plain comment, a doc comment would become the about text of `hasher`.
+#[derive(StructOpt)]
+struct MineArgs {
+    /// Student number the solutions are credited to.
+    #[structopt(long)]
+    student_number: Option<String>,
+    /// Name of this machine as shown by the pool.
+    #[structopt(long)]
+    name: Option<String>,
+    /// Number of mining threads, defaults to one less than the number of
+    /// CPUs.
+    #[structopt(long)]
+    threads: Option<usize>,
+    /// Address of the mining pool, e.g. http://localhost:9876
+    #[structopt(long)]
+    server: Option<String>,
+    /// Hashing implementation: scalar, blocks, sse4.1 or avx2. Defaults to
+    /// the fastest one on this CPU, measured at startup.
+    #[structopt(long)]
+    hash_backend: Option<BackendKind>,
+    /// Minimum number of leading zero bits of a solution, replacing the
+    /// difficulty set by the pool. Meant for local testing.
+    #[structopt(long)]
+    difficulty: Option<u8>,
+    /// Seconds to wait for a connection to the pool.
+    #[structopt(long)]
+    connect_timeout: Option<u64>,
+    /// Seconds to wait for the pool to answer a request.
+    #[structopt(long)]
+    read_timeout: Option<u64>,
+    /// Keep a WebSocket open to the pool, which then pushes jobs, stale
+    /// jobs, difficulty changes and messages instead of being polled.
+    #[structopt(long)]
+    push: bool,
+    /// PEM bundle of certificate authorities to trust, e.g. the one of a
+    /// self-hosted https:// pool.
+    #[structopt(long, parse(from_os_str))]
+    ca_file: Option<PathBuf>,
+    /// PEM certificate to present to the pool. Needs the `tls` feature, as
+    /// does --ca-file.
+    #[structopt(long, parse(from_os_str))]
+    client_cert: Option<PathBuf>,
+    /// PEM private key of the client certificate, if not in its file.
+    #[structopt(long, parse(from_os_str))]
+    client_key: Option<PathBuf>,
+    /// TOML file with the secret shared with the pool, and the scheme:
+    /// `secret = "..."` and `scheme = "hmac"` or `"token"`.
+    #[structopt(long, parse(from_os_str))]
+    auth_file: Option<PathBuf>,
+    /// Proxy for the pool connections: http://, socks5:// or socks5h://,
+    /// with user:password@ if needed. "none" ignores HTTP_PROXY,
+    /// HTTPS_PROXY and ALL_PROXY, which are used otherwise.
+    #[structopt(long)]
+    proxy: Option<String>,
+    /// Run without the terminal interface and write the log to stdout and
+    /// stderr. Stops on SIGINT or SIGTERM.
+    #[structopt(long)]
+    headless: bool,
+    /// Append the log to this file as well.
+    #[structopt(long, parse(from_os_str))]
+    log_file: Option<PathBuf>,
+    /// Keep the submissions the pool could not be reached for in this file,
+    /// defaults to hasher/hasher-queue.jsonl in the XDG state directory.
+    #[structopt(long, parse(from_os_str))]
+    queue_file: Option<PathBuf>,
+    /// Colours of the terminal interface: default, light or mono.
+    #[structopt(long)]
+    theme: Option<config::Theme>,
+}
 
-    // Check the student number is correct
-    if args.student_number.len() != 8 {
-        eprintln!("Student number must be 8 numbers.");
-        return Ok(());
-    }
-    for c in args.student_number.chars() {
-        if !c.is_digit(10) {
-            eprintln!("Student number should only contain numbers.");
-            return Ok(());
+impl MineArgs {
+    /// The settings given on the command line, they take precedence over
+    /// the environment and the configuration files.
+    fn settings(&self) -> config::Settings {
+        config::Settings {
+            server: self.server.clone(),
+            student_number: self.student_number.clone(),
+            machine_name: self.name.clone(),
+            threads: self.threads,
+            difficulty: self.difficulty,
+            connect_timeout: self.connect_timeout,
+            read_timeout: self.read_timeout,
+            push: if self.push { Some(true) } else { None },
+            ca_file: self.ca_file.clone(),
+            client_cert: self.client_cert.clone(),
+            client_key: self.client_key.clone(),
+            auth_file: self.auth_file.clone(),
+            proxy: self.proxy.clone(),
+            log_file: self.log_file.clone(),
+            queue_file: self.queue_file.clone(),
+            theme: self.theme,
+            keybindings: config::KeyBindingSettings::default(),
         }
     }
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Mine for the pool, the default when no subcommand is given.
+    Mine(MineArgs),
+    /// Measure the hash rate without connecting to the pool.
+    Bench {
+        /// Number of mining threads, defaults to one per CPU.
+        #[structopt(long)]
+        threads: Option<usize>,
+        /// How long to hash for, in seconds, at each thread count.
+        #[structopt(long, default_value = "10")]
+        duration: u64,
+        /// Try every thread count from 1 up to --threads and report where
+        /// the hash rate stops scaling.
+        #[structopt(long)]
+        sweep: bool,
+        /// Hashing implementation, defaults to the fastest one on this CPU,
+        /// measured at startup.
+        #[structopt(long)]
+        hash_backend: Option<BackendKind>,
+    },
+    /// Check a JSON file of solutions the way the pool would.
+    Verify {
+        /// Student number the solutions were mined for.
+        #[structopt(long)]
+        student_number: String,
+        /// A JSON array of solutions.
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// Minimum number of leading zero bits for a solution to be valid.
+        #[structopt(long, default_value = "34")]
+        min_zero_bits: u8,
+    },
+    /// Print the pool statistics of a student and exit.
+    Status {
+        /// Student number to look up, defaults to the configured one.
+        #[structopt(long)]
+        student_number: Option<String>,
+        /// Address of the mining pool.
+        #[structopt(long)]
+        server: Option<String>,
+    },
+    /// Host a mining pool, the same as `hasher-pool`.
+    Pool(pool::PoolArgs),
+    /// Inspect the configuration.
+    Config(ConfigAction),
+}
 
-    // Check miner name.
-    let machine_name = if let Some(n) = args.machine_name {
-        if n.len() == 0 {
-            eprintln!("The machine name must not be empty.");
-            return Ok(());
+#[derive(StructOpt)]
+enum ConfigAction {
+    /// Print the effective configuration and where each setting came from.
+    Show(MineArgs),
+}
+
+fn main() {
+
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    if let Some(rewritten) = legacy_args(&args) {
+        eprintln!(
+            "Positional arguments are deprecated, use \
+            `hasher mine --student-number <n> --name <name> --threads <n>`."
+        );
+        args = rewritten;
+    }
+    let args = Cli::from_iter(args);
+
+    let result = match args.command {
+        Some(Command::Mine(mine_args)) => mine(mine_args),
+        Some(Command::Bench { threads, duration, sweep, hash_backend }) => {
+            run_bench(threads, duration, sweep, hash_backend)
         }
-        n
-    } else {
-        String::from("i-o-restful-authentication-0")
+        Some(Command::Verify { student_number, file, min_zero_bits }) => {
+            verify_file(student_number, file, min_zero_bits)
+        }
+        Some(Command::Status { student_number, server }) => {
+            print_pool_status(config::Settings {
+                student_number,
+                server,
+                ..config::Settings::default()
+            })
+        }
+        Some(Command::Pool(pool_args)) => serve_pool(pool_args),
+        Some(Command::Config(ConfigAction::Show(mine_args))) => {
+            load_config(mine_args.settings()).map(|(config, _)| println!("{}", config.show()))
+        }
+        None => mine(args.mine),
     };
 
-    // Check thread count.
-    let thread_count = if let Some(thread_count) = args.thread_count {
-        thread_count
-    } else {
-        num_cpus::get() - 1
-    };
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(error.exit_code());
+    }
+}
+
+/// The positional form of old, `hasher <student number> [name] [threads]`,
+/// rewritten to `hasher mine` with flags so existing scripts keep working.
+/// Recognised before parsing by the 8 digit student number, which no
+/// subcommand is named like, so a machine named after a subcommand is still
+/// taken as the name. Returns `None` for any other command line.
+fn legacy_args(args: &[OsString]) -> Option<Vec<OsString>> {
+    let first = args.get(1)?.to_str()?;
+    if check_student_number(first).is_err() {
+        return None;
+    }
+    let mut rewritten = vec![args[0].clone(), OsString::from("mine")];
+    let flags = ["--student-number", "--name", "--threads"];
+    let positionals = args[1..].iter()
+        .take_while(|arg| !arg.to_string_lossy().starts_with('-'))
+        .take(flags.len())
+        .count();
+    for (flag, value) in flags.iter().zip(&args[1..1 + positionals]) {
+        rewritten.push(OsString::from(flag));
+        rewritten.push(value.clone());
+    }
+    rewritten.extend_from_slice(&args[1 + positionals..]);
+    Some(rewritten)
+}
+
+/// Merge the configuration files, the environment and the command line, in
+/// increasing precedence. Besides the configuration returns the environment
+/// and command line layers, to apply them again when the files are
+/// reloaded.
+fn load_config(command_line: config::Settings) -> Result<(config::Config, Vec<config::Layer>), CliError> {
+    let overrides = vec![
+        config::Layer::new("environment", config::Settings::from_env().map_err(CliError::Config)?),
+        config::Layer::new("command line", command_line),
+    ];
+    let config = config::Config::load(&overrides).map_err(CliError::Config)?;
+    Ok((config, overrides))
+}
+
+/// The configured student number, checked.
+fn student_number(config: &config::Config) -> Result<String, CliError> {
+    let student_number = config.student_number.clone()
+        .ok_or_else(|| CliError::InvalidIdentity(String::from("A student number is required.")))?;
+    check_student_number(&student_number)
+        .map_err(|message| CliError::InvalidIdentity(String::from(message)))?;
+    Ok(student_number)
+}
+
+/// Check the hashing backend, or measure which is fastest.
+fn hash_backend(hash_backend: Option<BackendKind>) -> Result<BackendKind, CliError> {
+    let hash_backend = hash_backend.unwrap_or_else(BackendKind::detect);
+    if !hash_backend.is_supported() {
+        return Err(CliError::Config(format!(
+            "The {} hash backend is not supported by this CPU.",
+            hash_backend,
+        )));
+    }
+    Ok(hash_backend)
+}
+
+fn mine(args: MineArgs) -> Result<(), CliError> {
+    let (config, overrides) = load_config(args.settings())?;
+    let student_number = student_number(&config)?;
+    if config.machine_name.is_empty() {
+        return Err(CliError::InvalidIdentity(String::from("The machine name must not be empty.")));
+    }
+    let hash_backend = hash_backend(args.hash_backend)?;
 
-    let app = Arc::new(
-        Mutex::new(
-            application::Application::start(
-                args.student_number,
-                thread_count,
-                machine_name,
-            )
-        )
-    );
+    // Load submissions that could not be delivered in a previous run.
+    let submission_queue = SubmissionQueue::open(&config.queue_file)
+        .map_err(|e| CliError::Io(format!("Could not read {}: {}", config.queue_file.display(), e)))?;
 
+    let mut application = application::Application::start(
+        student_number,
+        config.threads,
+        config.machine_name.clone(),
+        config.server_url.clone(),
+        &config.connection,
+        submission_queue,
+        hash_backend,
+    ).map_err(CliError::Config)?;
+    application.difficulty_override = config.difficulty;
+    application.push = config.push;
+    if let Some(log_file) = &config.log_file {
+        application.log.open_file(log_file)
+            .map_err(|e| CliError::Io(format!("Could not open {}: {}", log_file.display(), e)))?;
+    }
+    let app = Arc::new(Mutex::new(application));
+
+    // Make sure the pool is there before starting anything. The attempts
+    // that failed are in the log, print it since no interface shows it.
+    if let Err(error) = net::register_with_the_server(App::from(&app)) {
+        headless::print_log(&app, 0);
+        return Err(CliError::Network(format!(
+            "Could not register with the pool at {}. {}",
+            config.server_url,
+            error,
+        )));
+    }
+
+    let signal_thread = signals::begin(
+        App::from(&app),
+        move |app| reload_config(app, &overrides),
+    ).map_err(|e| CliError::Io(format!("Could not handle signals: {}", e)))?;
     let miner_thread = miner::begin(Arc::clone(&app));
     let status_thread = status::begin(App::from(&app));
-    ui::main_loop(Arc::clone(&app))?;
+    let queue_thread = net::queue::begin(App::from(&app));
+    let dispatcher_thread = dispatcher::begin(App::from(&app));
+    let push_thread = net::push::begin(App::from(&app));
+    let result = if args.headless {
+        headless::main_loop(Arc::clone(&app))
+            .map(Some)
+            .map_err(|e| CliError::Io(e.to_string()))
+    } else {
+        ui::main_loop(Arc::clone(&app), config.theme, config.keybindings)
+            .map(|()| None)
+            .map_err(|e| CliError::Terminal(e.to_string()))
+    };
+
+    // However the interface ended, stop the threads, submit the work in
+    // progress and leave the pool.
+    app.lock().unwrap().quitting = true;
+    signal_thread.join().expect("Could not finish signal thread");
     status_thread.join().expect("Could not finish status threads");
+    queue_thread.join().expect("Could not finish submission queue thread");
     miner_thread.join().expect("Could not finish mining threads");
+    dispatcher_thread.join().expect("Could not finish job dispatcher thread");
+    push_thread.join().expect("Could not finish push thread");
     net::deregister_with_the_server(App::from(&app));
+    if let Ok(Some(written)) = result {
+        headless::print_log(&app, written);
+    }
+    result.map(|_| ())
+}
+
+/// Apply the configuration files again, on SIGHUP. Settings given on the
+/// command line or in the environment keep their precedence. The pool
+/// address, thread count, difficulty, timeouts, certificates, credentials
+/// and push connection take effect right away.
+fn reload_config(app: &mut application::Application, overrides: &[config::Layer]) {
+    let config = match config::Config::load(overrides) {
+        Ok(config) => config,
+        Err(message) => {
+            app.log.error(&message);
+            return;
+        }
+    };
+    if config.server_url != app.server_url || config.connection != app.connection {
+        match app.connect(&config.server_url, &config.connection) {
+            Ok(()) => app.log.info(&format!(
+                "Using the pool at {}, {}s to connect, {}s for an answer.",
+                config.server_url,
+                config.connection.timeouts.connect.as_secs(),
+                config.connection.timeouts.read.as_secs(),
+            )),
+            Err(message) => app.log.error(&message),
+        }
+    }
+    if config.threads != app.expected_thread_count {
+        app.log.info(&format!("Mining on {} thread(s).", config.threads));
+        app.expected_thread_count = config.threads;
+    }
+    app.difficulty_override = config.difficulty;
+    if config.push != app.push {
+        app.log.info(if config.push { "Opening the push connection." } else { "Closing the push connection." });
+        app.push = config.push;
+    }
+}
+
+fn check_student_number(student_number: &str) -> Result<(), &'static str> {
+    if student_number.len() != 8 {
+        return Err("Student number must be 8 numbers.");
+    }
+    if !student_number.chars().all(|c| c.is_ascii_digit()) {
+        return Err("Student number should only contain numbers.");
+    }
+    Ok(())
+}
+
+/// Run the benchmark and print the hash rate per thread and in total, or a
+/// table of every thread count when sweeping.
+fn run_bench(
+    threads: Option<usize>,
+    duration: u64,
+    sweep: bool,
+    hash_backend: Option<BackendKind>,
+) -> Result<(), CliError> {
+    let hash_backend = self::hash_backend(hash_backend)?;
+    let threads = threads.unwrap_or_else(num_cpus::get);
+    config::check_threads(threads).map_err(CliError::Config)?;
+    let duration = Duration::from_secs(duration);
+    println!("Benchmarking the {} backend on {} CPU(s).", hash_backend, num_cpus::get());
+
+    if !sweep {
+        let result = bench::run(hash_backend, threads, duration).map_err(CliError::Config)?;
+        for (id, rate) in result.per_thread.iter().enumerate() {
+            println!("  Thread {:>3}: {:>9.3} MH/s", id, rate / 1_000_000.0);
+        }
+        println!("  Total:      {:>9.3} MH/s", result.total() / 1_000_000.0);
+        return Ok(());
+    }
+
+    println!("Threads   Total MH/s   Per thread MH/s   Speedup");
+    let mut results: Vec<bench::BenchResult> = vec![];
+    for count in 1..=threads {
+        let result = bench::run(hash_backend, count, duration).map_err(CliError::Config)?;
+        let single = results.first().map(|r| r.total()).unwrap_or_else(|| result.total());
+        println!(
+            "{:>7}   {:>10.3}   {:>15.3}   {:>6.2}x",
+            count,
+            result.total() / 1_000_000.0,
+            result.total() / count as f64 / 1_000_000.0,
+            result.total() / single,
+        );
+        results.push(result);
+    }
+    println!("Scaling stops after {} thread(s).", bench::scaling_limit(&results));
+    Ok(())
+}
+
+/// Ask the pool for the statistics of a student once and print them.
+fn print_pool_status(command_line: config::Settings) -> Result<(), CliError> {
+    let (config, _) = load_config(command_line)?;
+    let student_number = student_number(&config)?;
+    let pool = net::connect(&config.server_url, &config.connection).map_err(CliError::Config)?;
+    let start = Instant::now();
+    let status = pool.status(&PoolStatusRequestPacket { student_number })
+        .map_err(|error| CliError::Network(error.to_string()))?;
+    let latency = start.elapsed();
+    println!("Pool:                  {}", config.server_url);
+    if let Some(proxy) = net::proxy::resolve(&config.server_url, config.connection.proxy.as_deref()).map_err(CliError::Config)? {
+        println!("Proxy:                 {} (from {})", proxy, proxy.source);
+    }
+    println!("Your hash rate:        {:.02} MH/s", status.user_total_hash_rate / 1_000_000.0);
+    println!("Your shares:           {}", status.user_total_shares);
+    println!("Pool shares:           {}", status.pool_total_shares);
+    println!("Pool best zero length: {}/48", status.pool_best_zero_length);
+    println!("Completed jobs:        {}", status.completed_jobs);
+    println!("Latency:               {} ms", latency.as_millis());
+    Ok(())
+}
+
+/// Host a mining pool until the process is stopped.
+fn serve_pool(args: pool::PoolArgs) -> Result<(), CliError> {
+    let config = args.config().map_err(CliError::Config)?;
+    pool::http::serve(Arc::new(pool::Pool::new(config))).map_err(CliError::Network)
+}
+
+/// Verify every solution in the file and print the outcome of each. Fails
+/// when any solution is invalid.
+fn verify_file(student_number: String, file: PathBuf, min_zero_bits: u8) -> Result<(), CliError> {
+    check_student_number(&student_number)
+        .map_err(|message| CliError::InvalidIdentity(String::from(message)))?;
+    let contents = std::fs::read_to_string(&file)
+        .map_err(|e| CliError::Io(format!("Could not read {}: {}", file.display(), e)))?;
+    let solutions: Vec<Solution> = serde_json::from_str(&contents)
+        .map_err(|e| CliError::Io(format!("Could not parse {}: {}", file.display(), e)))?;
+
+    let mut invalid = 0;
+    for solution in solutions.iter() {
+        match verify::verify_solution(&student_number, solution, min_zero_bits) {
+            Ok(zero_bits) => println!("OK    {} {} ({} bits)", solution.nounce, solution.sha256, zero_bits),
+            Err(e) => {
+                invalid += 1;
+                println!("FAIL  {} {}: {}", solution.nounce, solution.sha256, e);
+            }
+        }
+    }
+    println!("{} of {} solutions valid.", solutions.len() - invalid, solutions.len());
+    if invalid > 0 {
+        return Err(CliError::InvalidSolutions { invalid, total: solutions.len() });
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Cli {
+        let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        Cli::from_iter_safe(legacy_args(&args).unwrap_or(args)).unwrap()
+    }
+
+    #[test]
+    fn legacy_positionals_mine_even_with_a_subcommand_as_name() {
+        match parse(&["hasher", "12345678", "bench", "0", "--server", "http://localhost:9876"]).command {
+            Some(Command::Mine(args)) => {
+                assert_eq!(args.student_number.as_deref(), Some("12345678"));
+                assert_eq!(args.name.as_deref(), Some("bench"));
+                assert_eq!(args.threads, Some(0));
+                assert_eq!(args.server.as_deref(), Some("http://localhost:9876"));
+            }
+            _ => panic!("not taken as mining"),
+        }
+        match parse(&["hasher", "12345678"]).command {
+            Some(Command::Mine(args)) => assert_eq!(args.name, None),
+            _ => panic!("not taken as mining"),
+        }
+    }
+
+    #[test]
+    fn subcommands_are_not_taken_as_legacy_positionals() {
+        assert!(matches!(parse(&["hasher", "bench", "--threads", "2"]).command, Some(Command::Bench { .. })));
+        assert!(matches!(
+            parse(&["hasher", "verify", "--student-number", "12345678", "solutions.json"]).command,
+            Some(Command::Verify { .. })
+        ));
+    }
+}
@@ -1,31 +1,32 @@
 use sha2::{Digest, Sha256};
+use sha2::digest::generic_array::GenericArray;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
 use crate::{application::{App, Application, CurrentJob, HashRateHistory, MiningThread, ThreadState}, net::{
-        request_job,
+        queue,
         submit_job,
         packets::{
+            Job,
             Solution,
             SubmittionPacket,
         },
     }};
-use radix_fmt::radix;
+use crate::dispatcher;
+use crate::hashing::{self, HashBackend};
+use crate::verify::nounce_at;
 use std::time::SystemTime;
 
-const MINIMUN_ZERO_BIT_LENGTH: u8 = 34;
-
 pub fn begin(app: Arc<Mutex<Application>>) -> std::thread::JoinHandle<()> {
     let maintaince_thread = std::thread::spawn(move || {
-        crate::net::register_with_the_server(App::from(&app));
         loop {
             let expected_thread_count = {
                 let app = app.lock().expect("Could not lock application.");
-                (*app).expected_thread_count
+                app.expected_thread_count
             };
             let mut active_thread_count = {
                 let app = app.lock().expect("Could not lock application.");
-                (*app).threads.len()
+                app.threads.len()
             };
 
             // Check thread count matches number of running threads.
@@ -34,7 +35,7 @@ pub fn begin(app: Arc<Mutex<Application>>) -> std::thread::JoinHandle<()> {
                     let miner = create_mining_thread(Arc::clone(&app));
                     { // Lock App
                         let mut app = app.lock().expect("Could not lock application.");
-                        (*app).threads.push(miner);
+                        app.threads.push(miner);
                     } // Unlock App
                 }
             } else if active_thread_count > expected_thread_count {
@@ -43,8 +44,8 @@ pub fn begin(app: Arc<Mutex<Application>>) -> std::thread::JoinHandle<()> {
                 while active_thread_count > expected_thread_count {
                     let miner = { // Lock App
                         let mut app = app.lock().expect("Could not lock application.");
-                        let m = (*app).threads.pop().unwrap();
-                        active_thread_count = (*app).threads.len();
+                        let m = app.threads.pop().unwrap();
+                        active_thread_count = app.threads.len();
                         m
                     }; // Unlock App
                     // Signal to the miner to end.
@@ -59,10 +60,10 @@ pub fn begin(app: Arc<Mutex<Application>>) -> std::thread::JoinHandle<()> {
             // Check if the miners need to end. If so, clean up.
             {
                 let mut app = app.lock().expect("Could not lock application.");
-                if (*app).quitting {
-                    (*app).expected_thread_count = 0;
+                if app.quitting {
+                    app.expected_thread_count = 0;
                     if active_thread_count == 0 {
-                        (*app).threads_cleaned_up = true;
+                        app.threads_cleaned_up = true;
                         break;
                     }
                 }
@@ -109,47 +110,53 @@ fn create_mining_thread(app: Arc<Mutex<Application>>) -> MiningThread {
 
 fn mining_loop(mut app: App, current_job: Arc<Mutex<Option<CurrentJob>>>, state: Arc<Mutex<ThreadState>>, hash_rate_history: Arc<Mutex<HashRateHistory>>) {
 
-    // Fetch job from server
-    let job_response = request_job(App::clone(&app));
-    let job  = if let Ok(job_response) = job_response {
-        let job_number = job_response.number;
-        let job_size = job_response.size;
+    let student_number = app.lock(|app| app.student_number.clone());
+    let backend = match app.lock(|app| app.hash_backend).build(student_number.as_bytes()) {
+        Some(backend) => backend,
+        None => {
+            app.lock(|app| {
+                app.log.error(&format!("The {} hash backend is not supported by this CPU.", app.hash_backend));
+                app.quitting = true;
+            });
+            return;
+        }
+    };
+
+    // Take the next job fetched by the dispatcher
+    let mut job = match dispatcher::take_job(App::clone(&app), &state) {
+        Some(job) => job,
+        None => return,
+    };
+    {
         let mut current_job = current_job.lock().unwrap();
         *current_job = Some(CurrentJob {
-            job_number,
-            size: job_size,
-            progress: 0,
+            job_number: job.number,
+            size: job.size,
+            progress: job.offset,
             solutions: 0,
         });
-        job_response
-    } else {
-        // Error reported to log screen by request_job(...).
-        app.lock(|app| app.log.error("Cannot aquire job. Waiting 10 seconds..."));
-        for _ in 0..1000 {
-            let state = state.lock().unwrap();
-            if *state == ThreadState::StopSignal {
-                return;
+    }
+
+    // A difficulty given on the command line replaces the pool's.
+    let pool_difficulty = job.difficulty;
+    if let Some(difficulty) = app.lock(|app| app.difficulty_override) {
+        job.difficulty = difficulty;
+    }
+    let difficulty = job.difficulty;
+    app.lock(|app| {
+        if app.difficulty != Some(difficulty) {
+            app.difficulty = Some(difficulty);
+            app.log.info(&format!("Difficulty target: {} leading zero bits.", difficulty));
+            if difficulty < pool_difficulty {
+                app.log.error(&format!(
+                    "The difficulty override is below the pool's {} bits, the pool will refuse most solutions.",
+                    pool_difficulty,
+                ));
             }
-            std::thread::sleep(Duration::from_millis(10));
         }
-        return;
-    };
+    });
 
     // Work on job.
-    let mut buffer: Vec<u8> = vec![]; // To hash.
-    // Add student number to buffer.
-    let student_number = app.lock(|app| app.student_number.clone());
-    student_number.chars().for_each(|c| buffer.push(c as u8));
-    // Add Initial nounce to buffer.
-    radix(job.nounce_start, 36).to_string().to_uppercase().chars().for_each(|c| buffer.push(c as u8));
-    // save work by storing sn len
-    let sn_len = student_number.len();
-    // Store found solutions in
-    let mut solutions = Vec::new();
-    // use SHA256
-    let mut sh = Sha256::default();
-    // store the latest nounce
-    let mut latest_nounce = job.nounce_start;
     // Capture batch job start time
     let start_time = get_time();
     // set state
@@ -157,13 +164,13 @@ fn mining_loop(mut app: App, current_job: Arc<Mutex<Option<CurrentJob>>>, state:
         let mut state = state.lock().unwrap();
         *state = ThreadState::Mining;
     }
-    // Compute hashs - Start
-    for nounce in job.nounce_start..job.nounce_end {
-        // Check if thread must report its status
-        if nounce % 10_000 == 0 {
+    let HashedRange { nounce_end, solutions } = hash_job(
+        backend.as_ref(),
+        &job,
+        |nounce, solutions| {
             // Check if the thread needs to stop
             if *state.lock().unwrap() == ThreadState::StopSignal {
-                break;
+                return false;
             }
             // Status update
             let mut current_job = current_job.lock().unwrap();
@@ -173,39 +180,27 @@ fn mining_loop(mut app: App, current_job: Arc<Mutex<Option<CurrentJob>>>, state:
                 progress: nounce - job.nounce_start,
                 solutions: solutions.len(),
             });
-                // Calc hashs / sec
-            let hashes = latest_nounce - job.nounce_start;
+            // Calc hashs / sec
+            let hashes = nounce - job.nounce_start - job.offset;
             let duration = get_time() - start_time;
             let hashs_per_second = hashes as f64 / duration;
             if !hashs_per_second.is_nan() {
                 (*(hash_rate_history.lock().unwrap())).push_hashrate(hashs_per_second);
             }
-        }
-        latest_nounce = nounce;
-        // calculate hash
-        sh.update(&buffer);
-        let sha256_buffer = sh.finalize_reset();
-        let count = count_leading_zero_bits(&sha256_buffer);
-        if count >= MINIMUN_ZERO_BIT_LENGTH {
-            // Capture Solution.
-            let solution = Solution {
-                nounce: buffer[sn_len..].iter().map(|&b| b as char).collect(),
-                sha256: sha245_to_string(&sha256_buffer),
-                time: get_time(),
-            };
+            true
+        },
+        |solution, count| {
             app.lock(|app| app.log.solution(
                 &solution.sha256, &solution.nounce, count,
             ));
-            solutions.push(solution);
-        }
-        increment_byte_string(&mut buffer, sn_len);
-    } // Compute hashs - End
+        },
+    );
 
     // Capture batch job end time
     let end_time = get_time();
 
     // Calc hashs / sec
-    let hashes = latest_nounce - job.nounce_start;
+    let hashes = nounce_end - job.nounce_start - job.offset;
     let duration = end_time - start_time;
     let thread_hashes_per_second = hashes as f64 / duration;
     let total_hashes_per_second = app.lock(|app| app.total_hashrate());
@@ -213,19 +208,140 @@ fn mining_loop(mut app: App, current_job: Arc<Mutex<Option<CurrentJob>>>, state:
     let submittion = SubmittionPacket {
         job_n: job.number,
         name: app.lock(|app| app.name.clone()),
-        student_number: student_number,
+        student_number,
         thread_hashes_per_second,
         total_hashes_per_second,
         nounce_start: job.nounce_start,
-        nounce_end: latest_nounce + 1,
+        nounce_end,
         solutions,
+        offset: job.offset,
+        partial: nounce_end < job.nounce_end,
+        explain_rejections: true,
     };
 
-    // Report at the end.
-    let _ = submit_job(app, submittion);
+    // Report at the end, or with the part hashed before the thread was
+    // stopped so the pool can hand out the rest. Queue the submission when
+    // the pool cannot be reached.
+    if submit_job(App::clone(&app), &submittion, &|| *state.lock().unwrap() == ThreadState::StopSignal).is_err() {
+        queue::enqueue(app, submittion);
+    }
+}
+
+/// The part of a job that was hashed.
+pub struct HashedRange {
+    /// The first nonce that was not hashed.
+    pub nounce_end: u64,
+    pub solutions: Vec<Solution>,
 }
 
-fn sha245_to_string(sha256_buffer: &[u8]) -> String {
+/// Hash the nonces of a job in the order the pool expects them, keeping the
+/// hashes with at least `job.difficulty` leading zero bits. About every
+/// 10,000 nonces `report` is called with the next nonce and the solutions
+/// found so far; hashing stops early when it returns false. `found` is called
+/// with every solution and its number of leading zero bits.
+pub fn hash_job(
+    backend: &dyn HashBackend,
+    job: &Job,
+    mut report: impl FnMut(u64, &[Solution]) -> bool,
+    mut found: impl FnMut(&Solution, u8),
+) -> HashedRange {
+    // Hash a batch of nonces per call, the batch size depends on the backend.
+    let lanes = backend.lanes();
+    let mut nounces: Vec<Vec<u8>> = vec![vec![]; lanes];
+    let mut digests = vec![[0; 32]; lanes];
+    // Store found solutions in
+    let mut solutions = Vec::new();
+    // Initial nounce, skipping the part of the range that was already
+    // hashed. Incremented in place.
+    let mut nounce = job.nounce_start + job.offset;
+    let mut buffer = match nounce_at(job.nounce_start, job.offset) {
+        Some(buffer) => buffer.into_bytes(),
+        None => return HashedRange { nounce_end: nounce, solutions },
+    };
+    // Compute hashs - Start
+    let mut next_report = nounce;
+    while nounce < job.nounce_end {
+        // Check if thread must report its status
+        if nounce >= next_report {
+            next_report = nounce + 10_000;
+            if !report(nounce, &solutions) {
+                break;
+            }
+        }
+        // Fill every lane, lanes past the end of the job are ignored.
+        let batch = lanes.min((job.nounce_end - nounce) as usize);
+        for lane in nounces.iter_mut() {
+            lane.clone_from(&buffer);
+            increment_byte_string(&mut buffer, 0);
+        }
+        nounce += batch as u64;
+        // calculate hashes
+        backend.hash(&nounces, &mut digests);
+        for (lane, sha256_buffer) in nounces.iter().zip(digests.iter()).take(batch) {
+            let count = count_leading_zero_bits(sha256_buffer);
+            if count >= job.difficulty {
+                // Capture Solution.
+                let solution = Solution {
+                    nounce: lane.iter().map(|&b| b as char).collect(),
+                    sha256: sha245_to_string(sha256_buffer),
+                    time: get_time(),
+                };
+                found(&solution, count);
+                solutions.push(solution);
+            }
+        }
+    } // Compute hashs - End
+    HashedRange { nounce_end: nounce, solutions }
+}
+
+/// SHA-256 of a student number followed by a nonce. A student number and
+/// nonce fit in a single block, so the block is kept as a template with the
+/// student number in place. Each hash writes the nonce, the padding byte and
+/// the length into a copy and runs the compression function once.
+///
+/// Messages longer than a block, 55 bytes and more, are hashed by `sha2`
+/// from the state after the student number.
+#[derive(Clone)]
+pub struct NounceHasher {
+    template: [u8; 64],
+    prefix_length: usize,
+    midstate: Sha256,
+}
+
+impl NounceHasher {
+    pub fn new(student_number: &[u8]) -> Self {
+        let mut template = [0; 64];
+        if student_number.len() <= MAX_SINGLE_BLOCK_MESSAGE {
+            template[..student_number.len()].copy_from_slice(student_number);
+        }
+        let mut midstate = Sha256::default();
+        midstate.update(student_number);
+        NounceHasher { template, prefix_length: student_number.len(), midstate }
+    }
+
+    pub fn hash(&self, nounce: &[u8]) -> [u8; 32] {
+        let length = self.prefix_length + nounce.len();
+        if length > MAX_SINGLE_BLOCK_MESSAGE {
+            let mut sh = self.midstate.clone();
+            sh.update(nounce);
+            let mut sha256_buffer = [0; 32];
+            sha256_buffer.copy_from_slice(&sh.finalize());
+            return sha256_buffer;
+        }
+        let mut block = self.template;
+        block[self.prefix_length..length].copy_from_slice(nounce);
+        block[length] = 0x80;
+        block[56..].copy_from_slice(&((length as u64) * 8).to_be_bytes());
+        let mut state = hashing::H0;
+        sha2::compress256(&mut state, std::slice::from_ref(GenericArray::from_slice(&block)));
+        hashing::state_to_digest(&state)
+    }
+}
+
+/// The longest message that fits a single block with its padding.
+const MAX_SINGLE_BLOCK_MESSAGE: usize = 55;
+
+pub fn sha245_to_string(sha256_buffer: &[u8]) -> String {
     let mut result = String::new();
     for byte in sha256_buffer {
         result += &format!("{:02x}", byte);
@@ -242,7 +358,7 @@ fn get_time() -> f64 {
 }
 
 
-fn count_leading_zero_bits(buffer: &[u8]) -> u8 {
+pub fn count_leading_zero_bits(buffer: &[u8]) -> u8 {
     let mut leading_zero_bits = 0;
     for byte in buffer {
         match byte {
@@ -297,15 +413,55 @@ fn next(ascii_decimal: u8) -> u8 {
 }
 
 
-fn increment_byte_string(s: &mut Vec<u8>, start_index: usize) {
-    for i in start_index..s.len() {
-        let c = s[i];
-        let n = next(c);
-        s[i] = n;
-        if n != ('0' as u8) {
+pub fn increment_byte_string(s: &mut Vec<u8>, start_index: usize) {
+    for c in s[start_index..].iter_mut() {
+        let n = next(*c);
+        *c = n;
+        if n != b'0' {
             return;
         }
     }
     // If this point is reached the number needs to be grown.
-    s.push('1' as u8);
+    s.push(b'1');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use radix_fmt::radix;
+
+    const STUDENT_NUMBER: &[u8] = b"12345678";
+
+    /// Walk `count` nonces from `nounce_start` both ways the miner ever did:
+    /// incrementing the whole student number and nonce buffer and hashing
+    /// it, and incrementing the nonce alone and hashing it with
+    /// `NounceHasher`.
+    fn assert_hasher_matches_full_buffer(prefix: &[u8], nounce_start: u64, count: u64) {
+        let start = radix(nounce_start, 36).to_string().to_uppercase();
+        let mut buffer = prefix.to_vec();
+        buffer.extend(start.bytes());
+        let mut nounce = start.into_bytes();
+        let hasher = NounceHasher::new(prefix);
+        for i in 0..count {
+            assert_eq!(buffer[prefix.len()..], nounce[..], "nonce {} differs", i);
+            assert_eq!(Sha256::digest(&buffer)[..], hasher.hash(&nounce)[..], "hash of nonce {} differs", i);
+            increment_byte_string(&mut buffer, prefix.len());
+            increment_byte_string(&mut nounce, 0);
+        }
+    }
+
+    #[test]
+    fn block_template_matches_full_buffer_across_rollovers() {
+        // Single digit to two digits, and a four digit nonce rolling over to five.
+        assert_hasher_matches_full_buffer(STUDENT_NUMBER, 0, 50_000);
+        assert_hasher_matches_full_buffer(STUDENT_NUMBER, u64::from_str_radix("YZZZ", 36).unwrap(), 50_000);
+    }
+
+    #[test]
+    fn messages_longer_than_a_block_are_hashed_in_full() {
+        // 52 bytes, growing past 55 as the nonce rolls over to four digits.
+        let prefix = [b'7'; 52];
+        assert_hasher_matches_full_buffer(&prefix, u64::from_str_radix("ZZ", 36).unwrap() - 10, 20);
+        assert_hasher_matches_full_buffer(&prefix, u64::from_str_radix("ZZZZZ", 36).unwrap() - 10, 20);
+    }
 }
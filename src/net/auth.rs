@@ -0,0 +1,239 @@
+//! Proof that a request comes from the student it names. The pool shares a
+//! secret with each student, and requests carry either the secret itself
+//! as a bearer token or an HMAC-SHA256 signature made with it.
+//!
+//! A signature covers the time it was made, a random nonce, the endpoint
+//! and the body:
+//!
+//! ```text
+//! HMAC-SHA256(secret, "<unix seconds>\n<nonce>\n<endpoint>\n<body>")
+//! ```
+//!
+//! It is sent hex encoded in `X-Hasher-Signature`, with the time in
+//! `X-Hasher-Timestamp` and the nonce in `X-Hasher-Nonce`. The pool refuses
+//! signatures made more than `MAX_CLOCK_SKEW` away from its own clock, and
+//! signatures it has already seen within that window, so a captured request
+//! cannot be replayed. The nonce keeps two identical requests made in the
+//! same second apart. The secret never crosses the network.
+//!
+//! A token is simpler, but anyone who sees a request can reuse it, so it is
+//! only sent to https:// pools.
+//!
+//! The push connection sends the proof with its upgrade request, made over
+//! the subscription that follows with `/push` as the endpoint.
+//!
+//! Stratum has no headers, so the proof is the password of
+//! `mining.authorize`: `token:<secret>` or
+//! `hmac:<timestamp>:<nonce>:<signature>`,
+//! signed over the worker name with `mining.authorize` as the endpoint.
+
+use std::fmt;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac, NewMac};
+use rand::Rng;
+use serde::Deserialize;
+use sha2::Sha256;
+
+pub const AUTHORIZATION_HEADER: &str = "Authorization";
+pub const TIMESTAMP_HEADER: &str = "X-Hasher-Timestamp";
+pub const SIGNATURE_HEADER: &str = "X-Hasher-Signature";
+pub const NONCE_HEADER: &str = "X-Hasher-Nonce";
+
+/// The endpoint the push subscription is signed for.
+pub const PUSH_ENDPOINT: &str = "/push";
+
+/// Seconds a signature may be off the pool's clock, either way.
+pub const MAX_CLOCK_SKEW: u64 = 300;
+
+/// How requests prove the student knows the secret.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+    /// Send the secret as a bearer token.
+    Token,
+    /// Sign each request with the secret.
+    #[default]
+    Hmac,
+}
+
+/// The auth file of a miner.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CredentialsFile {
+    #[serde(default)]
+    scheme: Scheme,
+    secret: String,
+}
+
+/// The secret a student shares with the pool and how it is used. The
+/// secret is left out of `Debug`.
+#[derive(Clone, PartialEq)]
+pub struct Credentials {
+    pub scheme: Scheme,
+    secret: String,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials").field("scheme", &self.scheme).finish()
+    }
+}
+
+impl Credentials {
+    /// Read an auth file, a TOML file such as:
+    ///
+    /// ```toml
+    /// scheme = "hmac"  # or "token", hmac when left out
+    /// secret = "the secret shared with the pool"
+    /// ```
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let file: CredentialsFile = toml::from_str(&contents)
+            .map_err(|e| format!("Could not parse {}: {}", path.display(), e))?;
+        if file.secret.is_empty() {
+            return Err(format!("The secret in {} is empty.", path.display()));
+        }
+        Ok(Credentials { scheme: file.scheme, secret: file.secret })
+    }
+
+    /// The proof for a request to `endpoint` carrying `body`, made now.
+    pub fn prove(&self, endpoint: &str, body: &[u8]) -> Proof {
+        match self.scheme {
+            Scheme::Token => Proof::Token(self.secret.clone()),
+            Scheme::Hmac => {
+                let timestamp = unix_time();
+                let nonce = format!("{:016x}", rand::thread_rng().gen::<u64>());
+                let signature = sign(&self.secret, timestamp, &nonce, endpoint, body);
+                Proof::Signature { timestamp, nonce, signature }
+            }
+        }
+    }
+}
+
+/// What a request carries to show who sent it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Proof {
+    Token(String),
+    Signature { timestamp: u64, nonce: String, signature: String },
+}
+
+impl Proof {
+    /// The HTTP headers carrying the proof.
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Proof::Token(token) => vec![(AUTHORIZATION_HEADER, format!("Bearer {}", token))],
+            Proof::Signature { timestamp, nonce, signature } => vec![
+                (TIMESTAMP_HEADER, timestamp.to_string()),
+                (NONCE_HEADER, nonce.clone()),
+                (SIGNATURE_HEADER, signature.clone()),
+            ],
+        }
+    }
+
+    /// Read the proof from the headers of a request, `header` looking them
+    /// up by name. Returns `None` when the request carries none.
+    pub fn from_headers(header: impl Fn(&str) -> Option<String>) -> Result<Option<Self>, String> {
+        if let Some(authorization) = header(AUTHORIZATION_HEADER) {
+            return match authorization.strip_prefix("Bearer ") {
+                Some(token) => Ok(Some(Proof::Token(String::from(token.trim())))),
+                None => Err(format!("unsupported {} header", AUTHORIZATION_HEADER)),
+            };
+        }
+        match (header(TIMESTAMP_HEADER), header(NONCE_HEADER), header(SIGNATURE_HEADER)) {
+            (Some(timestamp), Some(nonce), Some(signature)) => Ok(Some(Proof::Signature {
+                timestamp: timestamp.trim()
+                    .parse()
+                    .map_err(|_| format!("invalid {} \"{}\"", TIMESTAMP_HEADER, timestamp))?,
+                nonce: String::from(nonce.trim()),
+                signature: signature.trim().to_lowercase(),
+            })),
+            (None, None, None) => Ok(None),
+            _ => Err(format!("{}, {} and {} go together", TIMESTAMP_HEADER, NONCE_HEADER, SIGNATURE_HEADER)),
+        }
+    }
+
+    /// The proof as a stratum password.
+    pub fn to_password(&self) -> String {
+        match self {
+            Proof::Token(token) => format!("token:{}", token),
+            Proof::Signature { timestamp, nonce, signature } => format!("hmac:{}:{}:{}", timestamp, nonce, signature),
+        }
+    }
+
+    /// Read the proof from a stratum password. Returns `None` when the
+    /// password is empty.
+    pub fn from_password(password: &str) -> Result<Option<Self>, String> {
+        if password.is_empty() {
+            return Ok(None);
+        }
+        if let Some(token) = password.strip_prefix("token:") {
+            return Ok(Some(Proof::Token(String::from(token))));
+        }
+        let signature = password.strip_prefix("hmac:").and_then(|rest| {
+            let mut fields = rest.splitn(3, ':');
+            let timestamp = fields.next()?.parse().ok()?;
+            let nonce = String::from(fields.next()?);
+            Some(Proof::Signature { timestamp, nonce, signature: fields.next()?.to_lowercase() })
+        });
+        signature
+            .map(Some)
+            .ok_or_else(|| String::from("the password is neither token:<secret> nor hmac:<timestamp>:<nonce>:<signature>"))
+    }
+
+    /// Check the proof against the student's secret, `now` being the
+    /// pool's clock in Unix seconds. Whether a signature was used before is
+    /// up to the caller, see `pool::auth::Secrets`.
+    pub fn verify(&self, secret: &str, endpoint: &str, body: &[u8], now: u64) -> Result<(), String> {
+        match self {
+            Proof::Token(token) => {
+                if !constant_time_eq(token.as_bytes(), secret.as_bytes()) {
+                    return Err(String::from("wrong token"));
+                }
+            }
+            Proof::Signature { timestamp, nonce, signature } => {
+                let skew = now.max(*timestamp) - now.min(*timestamp);
+                if skew > MAX_CLOCK_SKEW {
+                    return Err(format!(
+                        "signature made {}s away from the pool's clock, at most {}s is allowed",
+                        skew,
+                        MAX_CLOCK_SKEW,
+                    ));
+                }
+                let expected = sign(secret, *timestamp, nonce, endpoint, body);
+                if !constant_time_eq(expected.as_bytes(), signature.as_bytes()) {
+                    return Err(String::from("wrong signature"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The hex encoded HMAC-SHA256 of a request, see the module documentation.
+pub fn sign(secret: &str, timestamp: u64, nonce: &str, endpoint: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC takes keys of any length.");
+    mac.update(format!("{}\n{}\n{}\n", timestamp, nonce, endpoint).as_bytes());
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Seconds since the Unix epoch.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+/// Compare without returning early, so the time taken does not tell how
+/// much of a guess was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |difference, (x, y)| difference | (x ^ y)) == 0
+}
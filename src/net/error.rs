@@ -0,0 +1,89 @@
+use std::error::Error;
+use std::fmt;
+
+/// Why a request to the pool failed. Every variant names the endpoint, e.g.
+/// `/job/request`, so the log tells which request went wrong.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetError {
+    /// No answer arrived: the connection failed, the name did not resolve or
+    /// the request timed out.
+    Transport { endpoint: String, message: String },
+    /// The pool answered with an HTTP error status.
+    Status { endpoint: String, status: u16 },
+    /// The answer is not the expected packet. The HTTP status is missing
+    /// for protocols other than HTTP.
+    Decode { endpoint: String, status: Option<u16>, message: String },
+    /// The pool understood the request but refused it, e.g. when no job is
+    /// left for us.
+    Server { endpoint: String, message: String },
+}
+
+impl NetError {
+    /// A failure to get any answer. Describes the innermost cause, which is
+    /// the useful part, e.g. "Connection refused".
+    pub fn transport(endpoint: &str, error: &reqwest::Error) -> Self {
+        let message = if error.is_timeout() {
+            String::from("timed out")
+        } else {
+            let mut cause: &dyn Error = error;
+            while let Some(source) = cause.source() {
+                cause = source;
+            }
+            cause.to_string()
+        };
+        NetError::Transport { endpoint: String::from(endpoint), message }
+    }
+
+    pub fn endpoint(&self) -> &str {
+        match self {
+            NetError::Transport { endpoint, .. }
+            | NetError::Status { endpoint, .. }
+            | NetError::Decode { endpoint, .. }
+            | NetError::Server { endpoint, .. } => endpoint,
+        }
+    }
+
+    /// The HTTP status of the answer, if one arrived.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            NetError::Status { status, .. } => Some(*status),
+            NetError::Decode { status, .. } => *status,
+            NetError::Transport { .. } | NetError::Server { .. } => None,
+        }
+    }
+
+    /// Whether the same request may succeed later. A pool that cannot be
+    /// reached, is overloaded or failed internally may recover, while a
+    /// request it refused or an answer it cannot encode stays the same.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            NetError::Transport { .. } => true,
+            NetError::Status { status, .. } => *status >= 500 || *status == 429 || *status == 408,
+            NetError::Decode { .. } | NetError::Server { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetError::Transport { endpoint, message } => {
+                write!(f, "Network error @ \"{}\": {}.", endpoint, message)
+            }
+            NetError::Status { endpoint, status } => {
+                write!(f, "HTTP error @ \"{}\": status {}.", endpoint, status)
+            }
+            NetError::Decode { endpoint, status: Some(status), message } => {
+                write!(f, "Response decode error @ \"{}\": {} (status {}).", endpoint, message, status)
+            }
+            NetError::Decode { endpoint, status: None, message } => {
+                write!(f, "Response decode error @ \"{}\": {}.", endpoint, message)
+            }
+            NetError::Server { endpoint, message } => {
+                write!(f, "Server error @ \"{}\": {}", endpoint, message)
+            }
+        }
+    }
+}
+
+impl Error for NetError {}
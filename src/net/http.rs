@@ -0,0 +1,219 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use super::{ConnectSettings, NetError};
+use super::auth::Credentials;
+use super::proxy::Proxy;
+use super::packets::{
+    BootRequest,
+    CommandResponse,
+    JobRequestPacket,
+    JobResponsePacket,
+    PoolStatusRequestPacket,
+    PoolStatusResponsePacket,
+    ShutdownRequest,
+    SubmittionPacket,
+    SubmittionResponsePacket,
+};
+use super::transport::{self, PoolTransport};
+
+/// How long requests to the pool may take.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timeouts {
+    /// Waiting for the connection to be established.
+    pub connect: Duration,
+    /// Waiting for the answer, from sending the request to reading the last
+    /// byte.
+    pub read: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            connect: Duration::from_secs(10),
+            read: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Who to trust and who to be on https:// connections. Needs the `tls`
+/// feature.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TlsSettings {
+    /// PEM file of certificate authorities trusted besides the built-in
+    /// ones, e.g. the one that signed the certificate of a self-hosted pool.
+    pub ca_file: Option<PathBuf>,
+    /// PEM file of the certificate chain presented to the pool.
+    pub client_cert: Option<PathBuf>,
+    /// PEM file of the private key of the client certificate. May be left
+    /// out when the key is in the certificate file.
+    pub client_key: Option<PathBuf>,
+}
+
+impl TlsSettings {
+    pub fn is_empty(&self) -> bool {
+        *self == TlsSettings::default()
+    }
+
+    #[cfg(feature = "tls")]
+    fn configure(&self, builder: reqwest::blocking::ClientBuilder) -> Result<reqwest::blocking::ClientBuilder, String> {
+        let read = |path: &PathBuf| {
+            std::fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))
+        };
+        let mut builder = builder.use_rustls_tls();
+        if let Some(ca_file) = &self.ca_file {
+            let certificate = reqwest::Certificate::from_pem(&read(ca_file)?)
+                .map_err(|e| format!("Invalid CA bundle {}: {}", ca_file.display(), e))?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        match (&self.client_cert, &self.client_key) {
+            (Some(client_cert), client_key) => {
+                let mut pem = read(client_cert)?;
+                if let Some(client_key) = client_key {
+                    pem.push(b'\n');
+                    pem.extend(read(client_key)?);
+                }
+                let identity = reqwest::Identity::from_pem(&pem)
+                    .map_err(|e| format!("Invalid client certificate {}: {}", client_cert.display(), e))?;
+                builder = builder.identity(identity);
+            }
+            (None, Some(_)) => return Err(String::from("A client key needs a client certificate.")),
+            (None, None) => {}
+        }
+        Ok(builder)
+    }
+
+    /// The same trust and identity for connections made without reqwest,
+    /// the push connection of https:// pools.
+    #[cfg(feature = "tls")]
+    pub fn client_config(&self) -> Result<rustls::ClientConfig, String> {
+        use rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+
+        let open = |path: &PathBuf| {
+            std::fs::File::open(path)
+                .map(std::io::BufReader::new)
+                .map_err(|e| format!("Could not read {}: {}", path.display(), e))
+        };
+        let mut config = rustls::ClientConfig::new();
+        config.root_store.add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+        if let Some(ca_file) = &self.ca_file {
+            config.root_store.add_pem_file(&mut open(ca_file)?)
+                .map_err(|_| format!("Invalid CA bundle {}.", ca_file.display()))?;
+        }
+        match (&self.client_cert, &self.client_key) {
+            (Some(client_cert), client_key) => {
+                let invalid = || format!("Invalid client certificate {}.", client_cert.display());
+                let chain = certs(&mut open(client_cert)?).map_err(|_| invalid())?;
+                let key_file = client_key.as_ref().unwrap_or(client_cert);
+                let mut keys = pkcs8_private_keys(&mut open(key_file)?).map_err(|_| invalid())?;
+                if keys.is_empty() {
+                    keys = rsa_private_keys(&mut open(key_file)?).map_err(|_| invalid())?;
+                }
+                let key = keys.into_iter().next().ok_or_else(invalid)?;
+                config.set_single_client_cert(chain, key).map_err(|e| format!("{}: {}", invalid(), e))?;
+            }
+            (None, Some(_)) => return Err(String::from("A client key needs a client certificate.")),
+            (None, None) => {}
+        }
+        Ok(config)
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn configure(&self, builder: reqwest::blocking::ClientBuilder) -> Result<reqwest::blocking::ClientBuilder, String> {
+        if !self.is_empty() {
+            return Err(String::from("CA and client certificates need hasher built with the `tls` feature."));
+        }
+        Ok(builder)
+    }
+}
+
+/// The HTTP client shared by every thread. It keeps connections to the pool
+/// alive between requests. Requests go through `proxy` only, the
+/// environment having been read by `proxy::resolve` already.
+pub fn build_client(timeouts: Timeouts, tls: &TlsSettings, proxy: Option<&Proxy>) -> Result<reqwest::blocking::Client, String> {
+    let mut builder = reqwest::blocking::Client::builder()
+        .connect_timeout(timeouts.connect)
+        .timeout(timeouts.read)
+        .tcp_keepalive(Duration::from_secs(60))
+        .pool_idle_timeout(Duration::from_secs(90))
+        .no_proxy();
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(proxy.to_reqwest()?);
+    }
+    tls.configure(builder)?
+        .build()
+        .map_err(|e| format!("Could not create the HTTP client: {}", e))
+}
+
+/// The pool protocol as JSON over HTTP, each operation POSTed to its own
+/// endpoint.
+pub struct HttpTransport {
+    client: reqwest::blocking::Client,
+    server_url: String,
+    credentials: Option<Credentials>,
+}
+
+impl HttpTransport {
+    pub fn new(server_url: &str, settings: &ConnectSettings, proxy: Option<&Proxy>) -> Result<Self, String> {
+        Ok(HttpTransport {
+            client: build_client(settings.timeouts, &settings.tls, proxy)?,
+            server_url: String::from(server_url),
+            credentials: settings.credentials.clone(),
+        })
+    }
+
+    /// POST a packet to an endpoint of the pool and decode the answer. The
+    /// body is signed as sent, when there are credentials.
+    fn post<T, U>(&self, uri: &str, packet: &T) -> Result<U, NetError>
+    where T: Serialize, U: DeserializeOwned
+    {
+        let body = serde_json::to_vec(packet).expect("Packets encode.");
+        let mut request = self.client
+            .post(format!("{}{}", self.server_url, uri))
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        if let Some(credentials) = &self.credentials {
+            for (name, value) in credentials.prove(uri, &body).headers() {
+                request = request.header(name, value);
+            }
+        }
+        let response = request
+            .body(body)
+            .send()
+            .map_err(|e| NetError::transport(uri, &e))?;
+
+        let status = response.status().as_u16();
+        if !response.status().is_success() {
+            return Err(NetError::Status { endpoint: String::from(uri), status });
+        }
+        response.json::<U>().map_err(|e| NetError::Decode {
+            endpoint: String::from(uri),
+            status: Some(status),
+            message: e.to_string(),
+        })
+    }
+}
+
+impl PoolTransport for HttpTransport {
+    fn boot(&self, request: &BootRequest) -> Result<CommandResponse, NetError> {
+        self.post(transport::BOOT, request)
+    }
+
+    fn request_job(&self, request: &JobRequestPacket) -> Result<JobResponsePacket, NetError> {
+        self.post(transport::REQUEST_JOB, request)
+    }
+
+    fn submit(&self, packet: &SubmittionPacket) -> Result<SubmittionResponsePacket, NetError> {
+        self.post(transport::SUBMIT, packet)
+    }
+
+    fn status(&self, request: &PoolStatusRequestPacket) -> Result<PoolStatusResponsePacket, NetError> {
+        self.post(transport::STATUS, request)
+    }
+
+    fn shutdown(&self, request: &ShutdownRequest) -> Result<CommandResponse, NetError> {
+        self.post(transport::SHUTDOWN, request)
+    }
+}
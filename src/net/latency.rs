@@ -0,0 +1,37 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Weight of the newest measurement in the moving average.
+const SMOOTHING: f64 = 0.2;
+
+/// How long the pool takes to answer, per endpoint.
+#[derive(Default)]
+pub struct Latencies(BTreeMap<String, Latency>);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Latency {
+    /// The latest round trip.
+    pub last: Duration,
+    /// Exponential moving average of the round trips.
+    pub average: Duration,
+    pub requests: u64,
+}
+
+impl Latencies {
+    /// Record the round trip of a request that got an answer.
+    pub fn record(&mut self, endpoint: &str, elapsed: Duration) {
+        let latency = self.0.entry(String::from(endpoint)).or_insert(Latency {
+            last: elapsed,
+            average: elapsed,
+            requests: 0,
+        });
+        latency.last = elapsed;
+        latency.average = latency.average.mul_f64(1.0 - SMOOTHING) + elapsed.mul_f64(SMOOTHING);
+        latency.requests += 1;
+    }
+
+    /// The endpoints in alphabetical order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Latency)> {
+        self.0.iter().map(|(endpoint, latency)| (endpoint.as_str(), latency))
+    }
+}
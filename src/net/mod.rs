@@ -1,101 +1,225 @@
+pub mod auth;
+pub mod error;
+pub mod http;
+pub mod latency;
 pub mod packets;
-// use serde::Deserialize;
-use serde::Serialize;
-use serde::de::DeserializeOwned;
+pub mod proxy;
+pub mod push;
+pub mod queue;
+pub mod retry;
+pub mod stratum;
+pub mod transport;
+use std::sync::Arc;
+use std::time::Instant;
 
 use crate::application::App;
 
+use self::retry::sleep_unless_stopped;
+use self::transport::PoolTransport;
+
+pub use self::error::NetError;
+pub use self::auth::Credentials;
+pub use self::http::{Timeouts, TlsSettings};
+
 use self::packets::{Job, JobResponsePacket, SubmittionPacket, SubmittionResponsePacket, PoolStatusRequestPacket, PoolStatusResponsePacket};
 
-const SERVER_URL: &str = "http://ec2-3-25-191-171.ap-southeast-2.compute.amazonaws.com:9876";
-
-fn api<T, U>(mut app: App, uri: &str, packet: T) -> Result<U, ()>
-where T: Serialize, U: DeserializeOwned
-{
-    let response = reqwest::blocking::Client::new()
-        .post(&format!("{}{}", SERVER_URL, uri))
-        .json(&packet)
-        .send();
-
-    if let Ok(response) = response {
-        let status_code = response.status();
-        if let Ok(response_packet) = response.json::<U>() {
-            Ok(response_packet)
-        } else {
-            app.lock( |app| {
-                app.log.error(
-                    &format!(
-                        "Response decode error @ \"{}\": invalid response. {}",
-                        uri,
-                        status_code
-                    )
-                );
-            });
-            Err(())
+/// The pool used when no server is given on the command line, in the
+/// environment or in the configuration file.
+pub const DEFAULT_SERVER_URL: &str = "http://ec2-3-25-191-171.ap-southeast-2.compute.amazonaws.com:9876";
+
+/// Check the pool address is a usable http(s) or stratum+tcp URL. The
+/// returned string has any trailing slash removed so endpoint paths can be
+/// appended directly.
+pub fn parse_server_url(server_url: &str) -> Result<String, String> {
+    let url = reqwest::Url::parse(server_url)
+        .map_err(|e| format!("Invalid server URL \"{}\": {}.", server_url, e))?;
+    if url.scheme() != "http" && url.scheme() != "https" && url.scheme() != stratum::SCHEME {
+        return Err(format!(
+            "Invalid server URL \"{}\": the scheme must be http, https or {}.",
+            server_url,
+            stratum::SCHEME,
+        ));
+    }
+    if url.scheme() == "https" && !cfg!(feature = "tls") {
+        return Err(format!(
+            "Invalid server URL \"{}\": https needs hasher built with the `tls` feature.",
+            server_url
+        ));
+    }
+    if url.host_str().is_none() {
+        return Err(format!("Invalid server URL \"{}\": missing host.", server_url));
+    }
+    Ok(String::from(server_url.trim_end_matches('/')))
+}
+
+/// How to talk to the pool, besides its address.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConnectSettings {
+    pub timeouts: Timeouts,
+    /// Applies to https:// pools.
+    pub tls: TlsSettings,
+    /// Proves requests come from the student, for pools that require it.
+    pub credentials: Option<Credentials>,
+    /// The `proxy` setting, see `net::proxy`. `None` leaves the choice to
+    /// the environment.
+    pub proxy: Option<String>,
+}
+
+/// The transport for a pool address: stratum for `stratum+tcp://`, HTTP
+/// otherwise.
+pub fn connect(server_url: &str, settings: &ConnectSettings) -> Result<Arc<dyn PoolTransport>, String> {
+    let url = reqwest::Url::parse(server_url)
+        .map_err(|e| format!("Invalid server URL \"{}\": {}.", server_url, e))?;
+    let proxy = proxy::resolve(server_url, settings.proxy.as_deref())?;
+    if url.scheme() == stratum::SCHEME {
+        let address = format!(
+            "{}:{}",
+            url.host_str().unwrap_or_default(),
+            url.port().unwrap_or(stratum::DEFAULT_PORT),
+        );
+        return Ok(Arc::new(stratum::StratumTransport::new(&address, settings, proxy)));
+    }
+    Ok(Arc::new(http::HttpTransport::new(server_url, settings, proxy.as_ref())?))
+}
+
+/// Send a request to the pool, retrying failed attempts according to the
+/// application's retry policy. Only failures that may go away are retried.
+/// Waiting between attempts ends early, with the last error, once
+/// `should_stop` reports true.
+fn api<U>(
+    mut app: App,
+    operation: &str,
+    should_stop: &dyn Fn() -> bool,
+    request: impl Fn(&dyn PoolTransport) -> Result<U, NetError>,
+) -> Result<U, NetError> {
+    let retry_policy = app.lock(|app| app.retry_policy);
+    let mut attempt = 1;
+    loop {
+        let error = match api_once(App::clone(&app), operation, &request) {
+            Ok(response) => return Ok(response),
+            Err(error) => error,
+        };
+        if !error.is_retryable() || attempt >= retry_policy.max_attempts {
+            return Err(error);
+        }
+        let delay = retry_policy.delay(attempt);
+        attempt += 1;
+        app.lock(|app| app.log.info(&format!(
+            "Retrying \"{}\" in {:.1}s (attempt {}/{}).",
+            operation,
+            delay.as_secs_f64(),
+            attempt,
+            retry_policy.max_attempts,
+        )));
+        if !sleep_unless_stopped(delay, should_stop) {
+            return Err(error);
         }
-    } else {
-        app.lock( |app| {
-            app.log.error("Network error: Could not register with the server.");
-        });
-        Err(())
     }
 }
 
-pub fn register_with_the_server(mut app: App) {
+/// Send a request to the pool once. Failures are logged and kept as the
+/// application's latest network error until a request succeeds.
+fn api_once<U>(
+    mut app: App,
+    operation: &str,
+    request: impl Fn(&dyn PoolTransport) -> Result<U, NetError>,
+) -> Result<U, NetError> {
+    let transport = app.lock(|app| Arc::clone(&app.transport));
+    let start = Instant::now();
+    let response = request(transport.as_ref());
+    let elapsed = start.elapsed();
+    app.lock(|app| {
+        if !matches!(response, Err(NetError::Transport { .. })) {
+            app.latencies.record(operation, elapsed);
+        }
+        match &response {
+            Ok(_) => app.network_error = None,
+            Err(error) => {
+                app.log.error(&error.to_string());
+                app.network_error = Some(error.clone());
+            }
+        }
+    });
+    response
+}
+
+/// Announce this machine to the pool.
+pub fn register_with_the_server(mut app: App) -> Result<(), NetError> {
     let packet = app.lock( |app| packets::BootRequest {
         name: app.name.clone(),
         student_number: app.student_number.clone(),
     });
-    let response = api::<_, packets::CommandResponse>(App::clone(&app), "/boot", packet);
-    if let Ok(_) = response {
-        app.lock(|app| app.log.info("Established connection with the server."));
-    }
+    api(App::clone(&app), transport::BOOT, &until_quitting(&app), |pool| pool.boot(&packet))?;
+    app.lock(|app| app.log.info("Established connection with the server."));
+    Ok(())
 }
 
+/// Tell the pool this machine is leaving. The application is already
+/// quitting, so there is a single attempt rather than the retry policy, and
+/// a pool that cannot be reached does not hold up the exit for longer than
+/// the request timeouts.
 pub fn deregister_with_the_server(mut app: App) {
     let packet = app.lock( |app| packets::ShutdownRequest {
         name: app.name.clone(),
         student_number: app.student_number.clone(),
     });
 
-    let _ = api::<_, packets::CommandResponse>(app, "/shutdown", packet);
+    let _ = api_once(app, transport::SHUTDOWN, |pool| pool.shutdown(&packet));
 }
 
-pub fn request_job(mut app: App) -> Result<Job, ()> {
+/// Ask the pool for a job. Retrying ends once `should_stop` reports true.
+pub fn request_job(mut app: App, should_stop: &dyn Fn() -> bool) -> Result<Job, NetError> {
     // Build request data
     let packet = app.lock( |app| packets::JobRequestPacket {
         student_number: app.student_number.clone(),
         name: app.name.clone(),
     });
 
-    let response = api::<_, packets::JobResponsePacket>(App::clone(&app), "/job/request", packet);
-    if let Ok(job_packet) = response {
-        match job_packet {
-            JobResponsePacket::Success(job) => {
-                Ok(job)
-            }
-            JobResponsePacket::Error(message) => {
-                let error_message = format!( "Server error: {}", message);
-                app.lock( |app| app.log.error(&error_message));
-                Err(())
-            }
+    match api(App::clone(&app), transport::REQUEST_JOB, should_stop, |pool| pool.request_job(&packet))? {
+        JobResponsePacket::Success(job) => Ok(job),
+        JobResponsePacket::Error(message) => {
+            let error = NetError::Server { endpoint: String::from(transport::REQUEST_JOB), message };
+            app.lock(|app| {
+                app.log.error(&error.to_string());
+                app.network_error = Some(error.clone());
+            });
+            Err(error)
         }
-    } else {
-        Err(())
     }
 }
 
-pub fn submit_job(app: App, packet: SubmittionPacket) -> Result<(), ()> {
+/// Submit a finished job. Returns `Ok` once the pool has answered, whether
+/// the job was accepted or rejected. Retrying ends once `should_stop`
+/// reports true.
+pub fn submit_job(app: App, packet: &SubmittionPacket, should_stop: &dyn Fn() -> bool) -> Result<(), NetError> {
+    let response = api(App::clone(&app), transport::SUBMIT, should_stop, |pool| pool.submit(packet))?;
+    record_submission_response(app, packet, response);
+    Ok(())
+}
 
-    let response = api::<_, SubmittionResponsePacket>(App::clone(&app), "/job/submit", packet);
-    if let Ok(_) = response {
-        Ok(())
-    } else {
-        Err(())
-    }
+/// Count the pool's answer to a submission and log rejections.
+fn record_submission_response(mut app: App, packet: &SubmittionPacket, response: SubmittionResponsePacket) {
+    let reason = match response {
+        SubmittionResponsePacket::Accepted => {
+            app.lock(|app| app.accepted_submissions += 1);
+            return;
+        }
+        SubmittionResponsePacket::Rejected => None,
+        SubmittionResponsePacket::RejectedWithReason(reason) => Some(reason),
+    };
+    let message = format!(
+        "Job {} with {} solution(s) rejected by the pool{}",
+        packet.job_n,
+        packet.solutions.len(),
+        reason.map(|reason| format!(": {}", reason)).unwrap_or_else(|| String::from(".")),
+    );
+    app.lock(|app| {
+        app.rejected_submissions += 1;
+        app.log.error(&message);
+    });
 }
 
-pub fn pool_status(mut app: App) -> Result<PoolStatusResponsePacket, ()> {
+pub fn pool_status(mut app: App) -> Result<PoolStatusResponsePacket, NetError> {
     let packet = {
         let student_number = app.lock(|app| app.student_number.clone());
         PoolStatusRequestPacket{
@@ -103,5 +227,210 @@ pub fn pool_status(mut app: App) -> Result<PoolStatusResponsePacket, ()> {
         }
     };
     
-    api::<_, PoolStatusResponsePacket>(App::clone(&app), "/status", packet)
-}
\ No newline at end of file
+    api(App::clone(&app), transport::STATUS, &until_quitting(&app), |pool| pool.status(&packet))
+}
+
+/// Give up on retries once the application is quitting.
+pub fn until_quitting(app: &App) -> impl Fn() -> bool {
+    let app = App::clone(app);
+    move || app.0.lock().unwrap().quitting
+}
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use super::packets::SubmittionPacket;
+    use super::queue::SubmissionQueue;
+    use super::retry::RetryPolicy;
+    use super::transport::FaultyTransport;
+    use crate::application::{Application, ThreadState};
+    use crate::hashing::BackendKind;
+    use crate::pool::{Pool, PoolConfig};
+    use crate::{dispatcher, miner};
+
+    const STUDENT_NUMBER: &str = "12345678";
+    const NAME: &str = "faulty";
+
+    /// An application talking to a fresh pool through a `FaultyTransport`,
+    /// retrying without waiting. Each test passes its own journal name.
+    fn start(journal: &str, max_attempts: u32) -> (App, Arc<FaultyTransport>, PathBuf) {
+        let pool = Arc::new(Pool::new(PoolConfig {
+            bind: String::new(),
+            stratum_bind: None,
+            job_size: 1_000_000,
+            min_zero_bits: 40,
+            workers: 1,
+            job_ttl: None,
+            secrets: None,
+        }));
+        let journal = std::env::temp_dir().join(format!("hasher-{}-{}.jsonl", journal, std::process::id()));
+        let _ = std::fs::remove_file(&journal);
+        let _ = std::fs::remove_file(journal.with_extension("failed.jsonl"));
+        let transport = Arc::new(FaultyTransport::new(pool));
+        let mut application = Application::start(
+            String::from(STUDENT_NUMBER),
+            1,
+            String::from(NAME),
+            // Replaced by the faulty transport below.
+            String::from("http://memory"),
+            &ConnectSettings::default(),
+            SubmissionQueue::open(&journal).unwrap(),
+            BackendKind::Scalar,
+        ).unwrap();
+        application.transport = Arc::clone(&transport) as Arc<dyn PoolTransport>;
+        application.retry_policy = RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+        };
+        (App(Arc::new(Mutex::new(application))), transport, journal)
+    }
+
+    /// An empty partial submission for a job fresh from the pool, the kind
+    /// the pool always accepts.
+    fn release(app: &App) -> SubmittionPacket {
+        let job = request_job(App::clone(app), &|| false).unwrap();
+        SubmittionPacket {
+            job_n: job.number,
+            name: String::from(NAME),
+            student_number: String::from(STUDENT_NUMBER),
+            thread_hashes_per_second: 0.0,
+            total_hashes_per_second: 0.0,
+            nounce_start: job.nounce_start,
+            nounce_end: job.nounce_start,
+            solutions: vec![],
+            offset: 0,
+            partial: true,
+            explain_rejections: true,
+        }
+    }
+
+    /// Wait for `done`, giving up after a few seconds.
+    fn wait_for(app: &mut App, done: impl Fn(&Application) -> bool) {
+        for _ in 0..500 {
+            if app.lock(|app| done(app)) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("timed out");
+    }
+
+    #[test]
+    fn transport_errors_are_retried() {
+        let (mut app, transport, _) = start("retried", 3);
+        transport.fail_transport(transport::STATUS, 2);
+        assert!(pool_status(App::clone(&app)).is_ok());
+        assert_eq!(transport.calls(transport::STATUS), 3);
+        assert!(app.lock(|app| app.network_error.is_none()));
+
+        // The policy gives up after its attempts, and keeps the error.
+        transport.fail_transport(transport::STATUS, 3);
+        assert!(matches!(pool_status(App::clone(&app)), Err(NetError::Transport { .. })));
+        assert_eq!(transport.calls(transport::STATUS), 6);
+        assert!(app.lock(|app| app.network_error.is_some()));
+    }
+
+    #[test]
+    fn refused_requests_are_not_retried() {
+        let (app, transport, _) = start("refused", 3);
+        transport.fail(transport::STATUS, NetError::Status { endpoint: String::from(transport::STATUS), status: 401 });
+        assert!(matches!(pool_status(app), Err(NetError::Status { status: 401, .. })));
+        assert_eq!(transport.calls(transport::STATUS), 1);
+    }
+
+    #[test]
+    fn deregistering_makes_a_single_attempt() {
+        let (app, transport, _) = start("deregister", 5);
+        transport.fail_transport(transport::SHUTDOWN, 1);
+        deregister_with_the_server(App::clone(&app));
+        assert_eq!(transport.calls(transport::SHUTDOWN), 1);
+
+        deregister_with_the_server(app);
+        assert_eq!(transport.calls(transport::SHUTDOWN), 2);
+    }
+
+    #[test]
+    fn queued_submissions_are_delivered_once_the_pool_answers() {
+        let (mut app, transport, journal) = start("queue", 2);
+        let packet = release(&app);
+        transport.fail_transport(transport::SUBMIT, 2);
+        assert!(submit_job(App::clone(&app), &packet, &|| false).is_err());
+        queue::enqueue(App::clone(&app), packet);
+        assert_eq!(app.lock(|app| app.submission_queue.len()), 1);
+        assert!(journal.exists());
+
+        let queue_thread = queue::begin(App::clone(&app));
+        wait_for(&mut app, |app| app.submission_queue.is_empty());
+        app.lock(|app| app.quitting = true);
+        queue_thread.join().unwrap();
+        assert_eq!(transport.calls(transport::SUBMIT), 3);
+        assert_eq!(app.lock(|app| app.accepted_submissions), 1);
+        assert!(!journal.exists());
+    }
+
+    #[test]
+    fn queued_submissions_the_pool_refuses_are_set_aside() {
+        let (mut app, transport, journal) = start("dead-letters", 1);
+        let refused = release(&app);
+        let delivered = release(&app);
+        queue::enqueue(App::clone(&app), refused.clone());
+        queue::enqueue(App::clone(&app), delivered);
+        transport.fail(transport::SUBMIT, NetError::Status { endpoint: String::from(transport::SUBMIT), status: 400 });
+
+        let queue_thread = queue::begin(App::clone(&app));
+        wait_for(&mut app, |app| app.submission_queue.is_empty());
+        app.lock(|app| app.quitting = true);
+        queue_thread.join().unwrap();
+        assert_eq!(app.lock(|app| app.accepted_submissions), 1);
+        let dead_letters = std::fs::read_to_string(journal.with_extension("failed.jsonl")).unwrap();
+        let set_aside: SubmittionPacket = serde_json::from_str(dead_letters.trim()).unwrap();
+        assert_eq!(set_aside.job_n, refused.job_n);
+        let _ = std::fs::remove_file(journal.with_extension("failed.jsonl"));
+    }
+
+    #[test]
+    fn miner_queues_what_it_cannot_submit() {
+        let (mut app, transport, journal) = start("miner", 1);
+        transport.fail_transport(transport::SUBMIT, 10);
+        let dispatcher_thread = dispatcher::begin(App::clone(&app));
+        let miner_thread = miner::begin(Arc::clone(&app.0));
+        wait_for(&mut app, |app| {
+            let progress = app.threads.first()
+                .and_then(|thread| thread.current_job.lock().unwrap().as_ref().map(|job| job.progress));
+            progress.unwrap_or(0) > 0
+        });
+        app.lock(|app| {
+            *app.threads[0].state.lock().unwrap() = ThreadState::StopSignal;
+            app.quitting = true;
+        });
+        miner_thread.join().unwrap();
+        dispatcher_thread.join().unwrap();
+
+        // The partial submission is queued. The job the dispatcher buffered
+        // is released with a single attempt and not queued.
+        let queued = app.lock(|app| app.submission_queue.front().cloned()).unwrap();
+        assert!(queued.partial);
+        assert!(queued.nounce_end > queued.nounce_start);
+        assert_eq!(app.lock(|app| app.submission_queue.len()), 1);
+        let _ = std::fs::remove_file(journal);
+    }
+
+    #[test]
+    fn dispatcher_backs_off_from_the_base_delay() {
+        let (mut app, transport, _) = start("backoff", 1);
+        // A wait of the full `max_delay` after a failed request would
+        // outlast `wait_for`.
+        app.lock(|app| app.retry_policy.max_delay = Duration::from_secs(60));
+        transport.fail_transport(transport::REQUEST_JOB, 3);
+        let dispatcher_thread = dispatcher::begin(App::clone(&app));
+        wait_for(&mut app, |app| !app.job_buffer.is_empty());
+        app.lock(|app| app.quitting = true);
+        dispatcher_thread.join().unwrap();
+        assert!(transport.calls(transport::REQUEST_JOB) >= 4);
+    }
+}
@@ -16,18 +16,50 @@ pub struct PoolStatusResponsePacket {
 }
 
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct JobRequestPacket {
     pub student_number: String,
     pub name: String,
 }
 
+/// Minimum number of leading zero bits of a solution, used for jobs from
+/// pools that do not send a difficulty.
+pub const DEFAULT_DIFFICULTY: u8 = 34;
+
+/// A range of nonces to hash. The miner starts from the base-36 form of
+/// `nounce_start` and hashes `nounce_end - nounce_start` nonces, see
+/// `verify::nounce_at` for the nonce at a given offset.
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct Job {
     pub number: u64,
     pub size: u64,
     pub nounce_start: u64,
     pub nounce_end: u64,
+    /// Minimum number of leading zero bits of a solution.
+    #[serde(default = "default_difficulty")]
+    pub difficulty: u8,
+    /// Number of nonces at the start of the range that were already hashed.
+    /// Non-zero when the pool reissues the rest of a partial submission.
+    #[serde(default)]
+    pub offset: u64,
+    /// Seconds the pool keeps the range reserved after handing out the job.
+    /// After that the job is stale: the range goes to another miner and the
+    /// submission is refused. `None` means the job never goes stale.
+    #[serde(default)]
+    pub ttl: Option<u64>,
+}
+
+fn default_difficulty() -> u8 {
+    DEFAULT_DIFFICULTY
+}
+
+/// Check a difficulty is usable. Every hash has at least zero leading zero
+/// bits, and the `u8` already stops anything above 255.
+pub fn check_difficulty(difficulty: u8) -> Result<(), String> {
+    if difficulty == 0 {
+        return Err(String::from("The difficulty must be between 1 and 255 leading zero bits."));
+    }
+    Ok(())
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -37,15 +69,18 @@ pub enum JobResponsePacket {
 }
 
 /// Solution info 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Solution {
     pub sha256: String,
     pub nounce: String,
     pub time: f64,
 }
 
-/// When the job is complete, this packet is sent to the pool.
-#[derive(Serialize, Deserialize, Debug)]
+/// When the job is complete, this packet is sent to the pool. A thread that
+/// is stopped early sends it too, with `partial` set. The nonces hashed are
+/// exactly those from `offset` up to `nounce_end - nounce_start`, so the
+/// pool can reissue the rest of the job.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SubmittionPacket {
     pub job_n: u64,
     pub name: String,
@@ -55,17 +90,30 @@ pub struct SubmittionPacket {
     pub nounce_start: u64,
     pub nounce_end: u64,
     pub solutions: Vec<Solution>,
+    /// The `offset` of the job.
+    #[serde(default)]
+    pub offset: u64,
+    /// The job was stopped before `nounce_end` reached the end of the job.
+    #[serde(default)]
+    pub partial: bool,
+    /// The client understands `RejectedWithReason`. Older clients leave it
+    /// out and are sent the plain `Rejected`.
+    #[serde(default)]
+    pub explain_rejections: bool,
 }
 
-/// Received from the server on job submission.
+/// Received from the server on job submission. Pools that explain why a
+/// submission was refused send `RejectedWithReason` to clients that set
+/// `explain_rejections`, older pools send the plain `Rejected`.
 #[derive(Serialize, Deserialize, Debug)]
 pub enum SubmittionResponsePacket {
     Accepted,
     Rejected,
+    RejectedWithReason(String),
 }
 
 /// Send a message informing the cloud the machine is active.
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BootRequest {
     pub student_number: String,
     pub name: String,
@@ -77,8 +125,25 @@ pub struct CommandResponse {
     pub msg: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ShutdownRequest {
     pub name: String,
     pub student_number: String,
-}
\ No newline at end of file
+}
+/// Sent by the pool over the push connection, see `net::push`. The miner
+/// subscribes by sending a `BootRequest` as the first message.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum PushMessage {
+    /// The next job of this machine, sent after each complete submission.
+    Job(Job),
+    /// The job with this number went stale and its range was given away,
+    /// its submission will be refused.
+    Stale(u64),
+    /// The difficulty of the jobs issued from now on.
+    Difficulty(u8),
+    /// A message from the pool operator to every miner.
+    Broadcast(String),
+    /// The statistics of the student, sent whenever the pool accepts a
+    /// submission.
+    Status(PoolStatusResponsePacket),
+}
@@ -0,0 +1,241 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tungstenite::{Message, WebSocket};
+
+use crate::application::App;
+
+use super::{auth, proxy, ConnectSettings, TlsSettings};
+use super::packets::{BootRequest, PushMessage};
+use super::retry::sleep_unless_stopped;
+
+/// How long the pool may stay silent before the connection counts as lost.
+/// The bundled pool pings every 15 seconds.
+const SILENCE_LIMIT: Duration = Duration::from_secs(45);
+
+/// How long a read waits before the thread checks whether it should stop.
+const READ_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The WebSocket address of a pool: `/push` on the same host and port,
+/// over TLS for https:// pools.
+pub fn push_url(server_url: &str) -> Result<String, String> {
+    let mut url = reqwest::Url::parse(server_url).map_err(|e| e.to_string())?;
+    let scheme = match url.scheme() {
+        "http" => "ws",
+        "https" => "wss",
+        _ => return Err(format!("push messages are only available from http(s):// pools, not {}", server_url)),
+    };
+    url.set_scheme(scheme).expect("http(s) and ws(s) are both special schemes.");
+    let path = format!("{}/push", url.path().trim_end_matches('/'));
+    url.set_path(&path);
+    Ok(url.to_string())
+}
+
+/// Start the thread holding the push connection, while `Application::push`
+/// is set. Over it the pool sends the next job after each submission, stale
+/// jobs, difficulty changes, broadcasts and the statistics, which then need
+/// not be polled. When the connection drops the other threads poll the pool
+/// again, until it is back.
+pub fn begin(mut app: App) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let quitting = super::until_quitting(&app);
+        let mut attempt = 0;
+        // The pool address without a push endpoint, reported once.
+        let mut unsupported: Option<String> = None;
+        while !quitting() {
+            let (enabled, server_url, settings, subscription) = app.lock(|app| (
+                app.push,
+                app.server_url.clone(),
+                app.connection.clone(),
+                BootRequest {
+                    student_number: app.student_number.clone(),
+                    name: app.name.clone(),
+                },
+            ));
+            if !enabled || unsupported.as_ref() == Some(&server_url) {
+                thread::sleep(READ_INTERVAL);
+                continue;
+            }
+            if let Err(message) = push_url(&server_url) {
+                app.lock(|app| app.log.error(&format!("No push connection: {}.", message)));
+                unsupported = Some(server_url);
+                continue;
+            }
+
+            let error = match connect(&server_url, &settings, &subscription) {
+                Ok(mut socket) => {
+                    attempt = 0;
+                    app.lock(|app| {
+                        app.push_connected = true;
+                        app.log.info("Receiving pool messages over the push connection.");
+                    });
+                    let result = listen(App::clone(&app), &mut socket, &server_url);
+                    app.lock(|app| app.push_connected = false);
+                    match result {
+                        Ok(()) => {
+                            let _ = socket.close(None);
+                            continue;
+                        }
+                        Err(message) => format!("Lost the push connection: {}.", message),
+                    }
+                }
+                Err(message) => format!("Could not open the push connection: {}.", message),
+            };
+            attempt += 1;
+            let delay = app.lock(|app| app.retry_policy.delay(attempt));
+            app.lock(|app| app.log.error(&format!(
+                "{} Polling the pool, trying again in {:.1}s.",
+                error,
+                delay.as_secs_f64(),
+            )));
+            sleep_unless_stopped(delay, &quitting);
+        }
+    })
+}
+
+/// Open the push connection and subscribe, through the pool's proxy if it
+/// has one. With credentials the upgrade request carries the proof, made
+/// over the subscription.
+fn connect(server_url: &str, settings: &ConnectSettings, subscription: &BootRequest) -> Result<WebSocket<PushStream>, String> {
+    let url = push_url(server_url)?;
+    let parsed = reqwest::Url::parse(&url).map_err(|e| e.to_string())?;
+    let address = format!(
+        "{}:{}",
+        parsed.host_str().unwrap_or_default(),
+        parsed.port_or_known_default().unwrap_or(80),
+    );
+    let proxy = proxy::resolve(server_url, settings.proxy.as_deref())?;
+    let stream = proxy::open(proxy.as_ref(), &address, settings.timeouts).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(settings.timeouts.read)).map_err(|e| e.to_string())?;
+    let stream = PushStream::new(&parsed, stream, &settings.tls)?;
+    let subscription = serde_json::to_string(subscription).expect("Subscriptions encode.");
+    let mut request = tungstenite::http::Request::get(url.as_str());
+    if let Some(credentials) = &settings.credentials {
+        for (name, value) in credentials.prove(auth::PUSH_ENDPOINT, subscription.as_bytes()).headers() {
+            request = request.header(name, value);
+        }
+    }
+    let request = request.body(()).map_err(|e| e.to_string())?;
+    let (mut socket, _) = tungstenite::client(request, stream).map_err(|e| e.to_string())?;
+    socket.write_message(Message::Text(subscription)).map_err(|e| e.to_string())?;
+    socket.get_ref().set_read_timeout(Some(READ_INTERVAL)).map_err(|e| e.to_string())?;
+    Ok(socket)
+}
+
+/// Handle the pool's messages. Returns `Ok` once the connection is no
+/// longer wanted: when quitting, when push messages are turned off or the
+/// pool address changed.
+fn listen(mut app: App, socket: &mut WebSocket<PushStream>, server_url: &str) -> Result<(), String> {
+    let mut last_heard = Instant::now();
+    loop {
+        if app.lock(|app| app.quitting || !app.push || app.server_url != server_url) {
+            return Ok(());
+        }
+        if last_heard.elapsed() > SILENCE_LIMIT {
+            return Err(format!("nothing heard for {}s", SILENCE_LIMIT.as_secs()));
+        }
+        match socket.read_message() {
+            Ok(Message::Text(text)) => {
+                last_heard = Instant::now();
+                let message = serde_json::from_str(&text).map_err(|e| format!("invalid message: {}", e))?;
+                handle(App::clone(&app), message);
+            }
+            Ok(Message::Close(_)) => return Err(String::from("closed by the pool")),
+            Ok(_) => last_heard = Instant::now(),
+            Err(tungstenite::Error::Io(e))
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+/// The connection under the WebSocket, encrypted for wss:// addresses.
+enum PushStream {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<rustls::StreamOwned<rustls::ClientSession, TcpStream>>),
+}
+
+impl PushStream {
+    #[cfg(feature = "tls")]
+    fn new(url: &reqwest::Url, stream: TcpStream, tls: &TlsSettings) -> Result<Self, String> {
+        if url.scheme() != "wss" {
+            return Ok(PushStream::Plain(stream));
+        }
+        let host = url.host_str().unwrap_or_default();
+        let name = webpki::DNSNameRef::try_from_ascii_str(host)
+            .map_err(|_| format!("{} is not a valid host name for TLS", host))?;
+        let session = rustls::ClientSession::new(&std::sync::Arc::new(tls.client_config()?), name);
+        Ok(PushStream::Tls(Box::new(rustls::StreamOwned::new(session, stream))))
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn new(url: &reqwest::Url, stream: TcpStream, _: &TlsSettings) -> Result<Self, String> {
+        if url.scheme() == "wss" {
+            return Err(String::from("wss:// needs hasher built with the `tls` feature"));
+        }
+        Ok(PushStream::Plain(stream))
+    }
+
+    fn get_ref(&self) -> &TcpStream {
+        match self {
+            PushStream::Plain(stream) => stream,
+            #[cfg(feature = "tls")]
+            PushStream::Tls(stream) => &stream.sock,
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.get_ref().set_read_timeout(timeout)
+    }
+}
+
+impl Read for PushStream {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self {
+            PushStream::Plain(stream) => stream.read(buffer),
+            #[cfg(feature = "tls")]
+            PushStream::Tls(stream) => stream.read(buffer),
+        }
+    }
+}
+
+impl Write for PushStream {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        match self {
+            PushStream::Plain(stream) => stream.write(buffer),
+            #[cfg(feature = "tls")]
+            PushStream::Tls(stream) => stream.write(buffer),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            PushStream::Plain(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            PushStream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+fn handle(mut app: App, message: PushMessage) {
+    match message {
+        PushMessage::Job(job) => app.lock(|app| app.job_buffer.push(job)),
+        PushMessage::Stale(job_number) => app.lock(|app| {
+            let message = if app.job_buffer.remove(job_number) {
+                format!("Job {} went stale at the pool, dropped it from the job buffer.", job_number)
+            } else {
+                format!("Job {} went stale at the pool, its submission will be refused.", job_number)
+            };
+            app.log.error(&message);
+        }),
+        PushMessage::Difficulty(difficulty) => app.lock(|app| app.log.info(&format!(
+            "The pool now issues jobs at difficulty {}.",
+            difficulty,
+        ))),
+        PushMessage::Broadcast(text) => app.lock(|app| app.log.info(&format!("Message from the pool: {}", text))),
+        PushMessage::Status(status) => app.lock(|app| app.pool_status = Some(status)),
+    }
+}
@@ -0,0 +1,218 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::application::App;
+
+use super::{api_once, record_submission_response, transport, until_quitting};
+use super::packets::SubmittionPacket;
+use super::retry::{jitter, sleep_unless_stopped};
+
+/// The name of the journal of unsent submissions. It is kept in the XDG
+/// state directory unless configured otherwise, see
+/// `config::default_queue_file`.
+pub const QUEUE_FILE_NAME: &str = "hasher-queue.jsonl";
+
+/// How long the background thread waits between attempts to empty the queue.
+const RETRY_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Submissions that could not be delivered to the pool. Every packet is
+/// written to a journal, one JSON object per line, so the queue survives a
+/// restart of the client.
+///
+/// The queue lives behind the application lock, so changing it only
+/// updates the packets in memory and returns a `Snapshot`. The caller
+/// writes the snapshot to the journal once the lock is released.
+pub struct SubmissionQueue {
+    pending: Vec<SubmittionPacket>,
+    journal: Arc<Journal>,
+    /// Counts the changes, so an older snapshot never overwrites a newer one.
+    version: u64,
+}
+
+/// The file behind a queue and the version of the queue it holds.
+struct Journal {
+    path: PathBuf,
+    written: Mutex<u64>,
+}
+
+/// The contents of the queue after a change, to be written to the journal.
+#[must_use = "the journal is only updated by writing the snapshot"]
+pub struct Snapshot {
+    journal: Arc<Journal>,
+    version: u64,
+    pending: Vec<SubmittionPacket>,
+}
+
+impl SubmissionQueue {
+    /// Open the journal at the given path, loading any submissions left over
+    /// from a previous run. Lines that cannot be decoded are dropped. The
+    /// directory of the journal is created when missing.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+            fs::create_dir_all(directory)?;
+        }
+        let mut pending = vec![];
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    if let Ok(packet) = serde_json::from_str(&line?) {
+                        pending.push(packet);
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(SubmissionQueue {
+            pending,
+            journal: Arc::new(Journal { path: path.to_path_buf(), written: Mutex::new(0) }),
+            version: 0,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Append a submission to the end of the queue.
+    pub fn push(&mut self, packet: SubmittionPacket) -> Snapshot {
+        self.pending.push(packet);
+        self.snapshot()
+    }
+
+    /// The file keeping the submissions the pool refused for good, next to
+    /// the journal.
+    pub fn dead_letters(&self) -> PathBuf {
+        self.journal.path.with_extension("failed.jsonl")
+    }
+
+    /// The oldest submission in the queue.
+    pub fn front(&self) -> Option<&SubmittionPacket> {
+        self.pending.first()
+    }
+
+    /// Remove the oldest submission once the pool has answered it.
+    pub fn pop_front(&mut self) -> Snapshot {
+        if !self.pending.is_empty() {
+            self.pending.remove(0);
+        }
+        self.snapshot()
+    }
+
+    fn snapshot(&mut self) -> Snapshot {
+        self.version += 1;
+        Snapshot {
+            journal: Arc::clone(&self.journal),
+            version: self.version,
+            pending: self.pending.clone(),
+        }
+    }
+}
+
+impl Snapshot {
+    /// Replace the journal with the snapshot, unless a later snapshot was
+    /// written first.
+    pub fn write(self) -> io::Result<()> {
+        let mut written = self.journal.written.lock().unwrap();
+        if *written >= self.version {
+            return Ok(());
+        }
+        let path = &self.journal.path;
+        if self.pending.is_empty() {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        } else {
+            let temporary_path = path.with_extension("jsonl.tmp");
+            {
+                let mut file = File::create(&temporary_path)?;
+                for packet in self.pending.iter() {
+                    writeln!(file, "{}", serde_json::to_string(packet)?)?;
+                }
+                file.sync_all()?;
+            }
+            fs::rename(&temporary_path, path)?;
+        }
+        *written = self.version;
+        Ok(())
+    }
+}
+
+/// Append a submission the pool will never accept to the dead letters.
+fn set_aside(path: &Path, packet: &SubmittionPacket) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(packet)?)
+}
+
+/// Store a submission the pool could not be reached for.
+pub fn enqueue(mut app: App, packet: SubmittionPacket) {
+    let job_n = packet.job_n;
+    let snapshot = app.lock(|app| app.submission_queue.push(packet.clone()));
+    let result = snapshot.write();
+    app.lock(|app| match &result {
+        Ok(()) => app.log.info(&format!("Job {} queued for a later submission.", job_n)),
+        Err(e) => app.log.error(&format!("Job {} queued, but could not be written to the journal: {}", job_n, e)),
+    });
+}
+
+/// Start the thread that resends queued submissions until the pool either
+/// accepts or rejects them. Submissions failing in a way that will not go
+/// away, see `NetError::is_retryable`, are moved to the dead letters.
+pub fn begin(mut app: App) -> std::thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let quitting = until_quitting(&app);
+        loop {
+            // Empty the queue, oldest submission first.
+            while let Some(packet) = app.lock(|app| app.submission_queue.front().cloned()) {
+                if quitting() {
+                    return;
+                }
+                let snapshot = match api_once(App::clone(&app), transport::SUBMIT, |pool| pool.submit(&packet)) {
+                    Ok(response) => {
+                        record_submission_response(App::clone(&app), &packet, response);
+                        app.lock(|app| {
+                            app.log.info(&format!("Delivered queued job {}.", packet.job_n));
+                            app.submission_queue.pop_front()
+                        })
+                    }
+                    // Try again later, keeping the order of the queue.
+                    Err(error) if error.is_retryable() => break,
+                    // The pool will never take this packet, set it aside so
+                    // it does not hold up the ones behind it.
+                    Err(error) => {
+                        let (snapshot, dead_letters) = app.lock(|app| {
+                            (app.submission_queue.pop_front(), app.submission_queue.dead_letters())
+                        });
+                        let message = match set_aside(&dead_letters, &packet) {
+                            Ok(()) => format!(
+                                "Gave up on queued job {}, it is kept in {}: {}",
+                                packet.job_n,
+                                dead_letters.display(),
+                                error,
+                            ),
+                            Err(e) => format!("Dropped queued job {}: {} Could not keep it: {}", packet.job_n, error, e),
+                        };
+                        app.lock(|app| app.log.error(&message));
+                        snapshot
+                    }
+                };
+                if let Err(e) = snapshot.write() {
+                    app.lock(|app| app.log.error(&format!("Could not update the submission queue: {}", e)));
+                }
+            }
+
+            if !sleep_unless_stopped(jitter(RETRY_INTERVAL), &quitting) {
+                return;
+            }
+        }
+    })
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+/// How failed requests to the pool are retried. The delay before each retry
+/// doubles, up to `max_delay`, and is randomised so threads that failed
+/// together do not all return to the server at the same moment.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry, before jitter is applied.
+    pub base_delay: Duration,
+    /// Upper bound of the delay between attempts.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay to wait after the given failed attempt (starting at 1).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let backoff = self.base_delay
+            .checked_mul(1 << exponent)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        jitter(backoff)
+    }
+}
+
+/// Pick a random duration between half and all of the given duration.
+pub fn jitter(duration: Duration) -> Duration {
+    let millis = duration.as_millis() as u64;
+    if millis < 2 {
+        return duration;
+    }
+    let half = millis / 2;
+    Duration::from_millis(half + rand::thread_rng().gen_range(0, millis - half + 1))
+}
+
+/// Sleep for the given duration in small steps, returning early with `false`
+/// as soon as `should_stop` reports true.
+pub fn sleep_unless_stopped(duration: Duration, should_stop: &dyn Fn() -> bool) -> bool {
+    let step = Duration::from_millis(10);
+    let mut slept = Duration::from_millis(0);
+    while slept < duration {
+        if should_stop() {
+            return false;
+        }
+        std::thread::sleep(step.min(duration - slept));
+        slept += step;
+    }
+    !should_stop()
+}
@@ -0,0 +1,353 @@
+//! The pool protocol as stratum-style JSON-RPC: one long-lived TCP
+//! connection carrying a JSON message per line. Selected with a
+//! `stratum+tcp://host:port` server address.
+//!
+//! The messages map onto the HTTP endpoints as follows:
+//!
+//! * `mining.subscribe` opens the session, there is no extranonce since the
+//!   pool hands out whole nonce ranges.
+//! * `mining.authorize` with the worker `<student number>.<machine name>`
+//!   announces the machine, like `/boot`. Closing the connection takes it
+//!   offline, like `/shutdown`. The password carries the proof of who the
+//!   student is, see `net::auth`, and is empty without credentials.
+//! * `mining.notify` delivers a `Job`, at the difficulty of the latest
+//!   `mining.set_difficulty`. The pool notifies a job after each complete
+//!   submission and when asked with `hasher.request_job`.
+//! * `mining.submit` submits a finished or partial job with its solutions.
+//! * `hasher.status` answers with the statistics of a student.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::Mutex;
+
+use serde_json::{json, Value};
+
+use super::{ConnectSettings, NetError, Timeouts};
+use super::auth::Credentials;
+use super::proxy::{self, Proxy};
+use super::packets::{
+    BootRequest,
+    CommandResponse,
+    Job,
+    JobRequestPacket,
+    JobResponsePacket,
+    PoolStatusRequestPacket,
+    PoolStatusResponsePacket,
+    ShutdownRequest,
+    Solution,
+    SubmittionPacket,
+    SubmittionResponsePacket,
+    DEFAULT_DIFFICULTY,
+};
+use super::transport::{self, PoolTransport};
+
+/// The URL scheme selecting this protocol.
+pub const SCHEME: &str = "stratum+tcp";
+
+/// The port used when the server address has none.
+pub const DEFAULT_PORT: u16 = 3333;
+
+pub const SUBSCRIBE: &str = "mining.subscribe";
+pub const AUTHORIZE: &str = "mining.authorize";
+pub const NOTIFY: &str = "mining.notify";
+pub const SET_DIFFICULTY: &str = "mining.set_difficulty";
+pub const SUBMIT: &str = "mining.submit";
+pub const REQUEST_JOB: &str = "hasher.request_job";
+pub const STATUS: &str = "hasher.status";
+
+/// Error code of a refused request, stratum's "other/unknown".
+pub const REFUSED: u32 = 20;
+
+/// The worker name of a machine.
+pub fn worker_name(student_number: &str, name: &str) -> String {
+    format!("{}.{}", student_number, name)
+}
+
+/// Split a worker name into the student number and the machine name.
+pub fn parse_worker_name(worker: &str) -> Option<(String, String)> {
+    let (student_number, name) = worker.split_at(worker.find('.')?);
+    Some((String::from(student_number), String::from(&name[1..])))
+}
+
+/// The `mining.notify` parameters of a job: number, first and last nonce,
+/// offset, time to live and whether older jobs should be dropped.
+pub fn notify_params(job: &Job) -> Value {
+    json!([job.number, job.nounce_start, job.nounce_end, job.offset, job.ttl, false])
+}
+
+fn job_from_notify(params: Value, difficulty: u8) -> Result<Job, String> {
+    let (number, nounce_start, nounce_end, offset, ttl, _clean_jobs) =
+        serde_json::from_value::<(u64, u64, u64, u64, Option<u64>, bool)>(params)
+            .map_err(|e| format!("invalid {}: {}", NOTIFY, e))?;
+    Ok(Job {
+        number,
+        size: nounce_end.saturating_sub(nounce_start),
+        nounce_start,
+        nounce_end,
+        difficulty,
+        offset,
+        ttl,
+    })
+}
+
+/// The `mining.submit` parameters: the worker followed by the fields of
+/// the packet.
+pub fn submit_params(packet: &SubmittionPacket) -> Value {
+    json!([
+        worker_name(&packet.student_number, &packet.name),
+        packet.job_n,
+        packet.nounce_start,
+        packet.nounce_end,
+        packet.offset,
+        packet.partial,
+        packet.solutions,
+        packet.thread_hashes_per_second,
+        packet.total_hashes_per_second,
+    ])
+}
+
+pub fn packet_from_submit(params: Value) -> Result<SubmittionPacket, String> {
+    type Params = (String, u64, u64, u64, u64, bool, Vec<Solution>, f64, f64);
+    let (worker, job_n, nounce_start, nounce_end, offset, partial, solutions, thread_rate, total_rate) =
+        serde_json::from_value::<Params>(params).map_err(|e| format!("invalid {}: {}", SUBMIT, e))?;
+    let (student_number, name) = parse_worker_name(&worker)
+        .ok_or_else(|| format!("invalid worker name \"{}\"", worker))?;
+    Ok(SubmittionPacket {
+        job_n,
+        name,
+        student_number,
+        thread_hashes_per_second: thread_rate,
+        total_hashes_per_second: total_rate,
+        nounce_start,
+        nounce_end,
+        solutions,
+        offset,
+        partial,
+        // Stratum carries the reason in the error of the call.
+        explain_rejections: true,
+    })
+}
+
+/// Why a call failed. Only `Refused` leaves the connection usable.
+enum Failure {
+    Io(io::Error),
+    /// The pool sent something that is not the expected message.
+    Protocol(String),
+    /// The pool answered with an error.
+    Refused(String),
+}
+
+impl Failure {
+    fn into_net_error(self, operation: &str) -> NetError {
+        let endpoint = String::from(operation);
+        match self {
+            Failure::Io(e) => {
+                let message = match e.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => String::from("timed out"),
+                    _ => e.to_string(),
+                };
+                NetError::Transport { endpoint, message }
+            }
+            Failure::Protocol(message) => NetError::Decode { endpoint, status: None, message },
+            Failure::Refused(message) => NetError::Server { endpoint, message },
+        }
+    }
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    next_id: u64,
+    /// The worker the connection is authorized as.
+    worker: Option<String>,
+    /// Difficulty of the jobs notified from now on.
+    difficulty: u8,
+    /// Jobs notified but not yet handed to the dispatcher.
+    jobs: VecDeque<Job>,
+}
+
+impl Connection {
+    fn open(address: &str, timeouts: Timeouts, proxy: Option<&Proxy>) -> Result<Self, Failure> {
+        let stream = proxy::open(proxy, address, timeouts).map_err(Failure::Io)?;
+        stream.set_read_timeout(Some(timeouts.read)).map_err(Failure::Io)?;
+        let mut connection = Connection {
+            reader: BufReader::new(stream.try_clone().map_err(Failure::Io)?),
+            writer: stream,
+            next_id: 1,
+            worker: None,
+            difficulty: DEFAULT_DIFFICULTY,
+            jobs: VecDeque::new(),
+        };
+        connection.call(SUBSCRIBE, json!([concat!("hasher/", env!("CARGO_PKG_VERSION"))]))?;
+        Ok(connection)
+    }
+
+    fn authorize(&mut self, worker: &str, credentials: Option<&Credentials>) -> Result<(), Failure> {
+        let password = credentials
+            .map(|credentials| credentials.prove(AUTHORIZE, worker.as_bytes()).to_password())
+            .unwrap_or_default();
+        match self.call(AUTHORIZE, json!([worker, password]))? {
+            Value::Bool(true) => {
+                self.worker = Some(String::from(worker));
+                Ok(())
+            }
+            _ => Err(Failure::Refused(format!("worker \"{}\" was not authorized", worker))),
+        }
+    }
+
+    /// Send a request and wait for its answer, keeping the notifications
+    /// that arrive in the meantime.
+    fn call(&mut self, method: &str, params: Value) -> Result<Value, Failure> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut line = json!({ "id": id, "method": method, "params": params }).to_string();
+        line.push('\n');
+        self.writer.write_all(line.as_bytes()).map_err(Failure::Io)?;
+
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line).map_err(Failure::Io)? == 0 {
+                return Err(Failure::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by the pool")));
+            }
+            let mut message: Value = serde_json::from_str(&line)
+                .map_err(|e| Failure::Protocol(format!("invalid message: {}", e)))?;
+            if let Some(method) = message.get("method").and_then(Value::as_str) {
+                let method = String::from(method);
+                self.notification(&method, message["params"].take())?;
+                continue;
+            }
+            if message["id"].as_u64() != Some(id) {
+                return Err(Failure::Protocol(format!("unexpected answer: {}", line.trim())));
+            }
+            return match message["error"].take() {
+                Value::Null => Ok(message["result"].take()),
+                error => Err(Failure::Refused(error_message(error))),
+            };
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: Value) -> Result<(), Failure> {
+        match method {
+            NOTIFY => self.jobs.push_back(job_from_notify(params, self.difficulty).map_err(Failure::Protocol)?),
+            SET_DIFFICULTY => {
+                self.difficulty = serde_json::from_value::<(u8,)>(params)
+                    .map_err(|e| Failure::Protocol(format!("invalid {}: {}", SET_DIFFICULTY, e)))?
+                    .0;
+            }
+            // Notifications of other extensions are of no use here.
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// The reason of a stratum error, `[code, message, data]`.
+fn error_message(error: Value) -> String {
+    match error {
+        Value::Array(fields) if fields.len() >= 2 => match &fields[1] {
+            Value::String(message) => message.clone(),
+            other => other.to_string(),
+        },
+        other => other.to_string(),
+    }
+}
+
+/// Talks to the pool over a single stratum connection, shared by every
+/// thread. The connection is opened on first use, reopened after it broke
+/// and authorized as the worker of each request.
+pub struct StratumTransport {
+    address: String,
+    timeouts: Timeouts,
+    credentials: Option<Credentials>,
+    proxy: Option<Proxy>,
+    connection: Mutex<Option<Connection>>,
+}
+
+impl StratumTransport {
+    /// A transport to `host:port`, through `proxy` if given.
+    pub fn new(address: &str, settings: &ConnectSettings, proxy: Option<Proxy>) -> Self {
+        StratumTransport {
+            address: String::from(address),
+            timeouts: settings.timeouts,
+            credentials: settings.credentials.clone(),
+            proxy,
+            connection: Mutex::new(None),
+        }
+    }
+
+    /// Run `call` on the connection, authorized as `worker` when given. A
+    /// connection that failed is dropped, to be reopened by the next call.
+    fn with_connection<U>(
+        &self,
+        operation: &str,
+        worker: Option<String>,
+        call: impl FnOnce(&mut Connection) -> Result<U, Failure>,
+    ) -> Result<U, NetError> {
+        let mut guard = self.connection.lock().unwrap();
+        let result = (|| {
+            if guard.is_none() {
+                *guard = Some(Connection::open(&self.address, self.timeouts, self.proxy.as_ref())?);
+            }
+            let connection = guard.as_mut().unwrap();
+            if let Some(worker) = worker {
+                if connection.worker.as_ref() != Some(&worker) {
+                    connection.authorize(&worker, self.credentials.as_ref())?;
+                }
+            }
+            call(connection)
+        })();
+        if let Err(Failure::Io(_)) | Err(Failure::Protocol(_)) = result {
+            *guard = None;
+        }
+        result.map_err(|failure| failure.into_net_error(operation))
+    }
+}
+
+impl PoolTransport for StratumTransport {
+    fn boot(&self, request: &BootRequest) -> Result<CommandResponse, NetError> {
+        let worker = worker_name(&request.student_number, &request.name);
+        self.with_connection(transport::BOOT, Some(worker), |_| Ok(CommandResponse { ok: true, msg: None }))
+    }
+
+    fn request_job(&self, request: &JobRequestPacket) -> Result<JobResponsePacket, NetError> {
+        let worker = worker_name(&request.student_number, &request.name);
+        self.with_connection(transport::REQUEST_JOB, Some(worker), |connection| {
+            if connection.jobs.is_empty() {
+                match connection.call(REQUEST_JOB, json!([])) {
+                    Ok(_) => {}
+                    Err(Failure::Refused(message)) => return Ok(JobResponsePacket::Error(message)),
+                    Err(failure) => return Err(failure),
+                }
+            }
+            connection.jobs
+                .pop_front()
+                .map(JobResponsePacket::Success)
+                .ok_or_else(|| Failure::Protocol(String::from("no job was notified")))
+        })
+    }
+
+    fn submit(&self, packet: &SubmittionPacket) -> Result<SubmittionResponsePacket, NetError> {
+        let worker = worker_name(&packet.student_number, &packet.name);
+        self.with_connection(transport::SUBMIT, Some(worker), |connection| {
+            match connection.call(SUBMIT, submit_params(packet)) {
+                Ok(_) => Ok(SubmittionResponsePacket::Accepted),
+                Err(Failure::Refused(reason)) => Ok(SubmittionResponsePacket::RejectedWithReason(reason)),
+                Err(failure) => Err(failure),
+            }
+        })
+    }
+
+    fn status(&self, request: &PoolStatusRequestPacket) -> Result<PoolStatusResponsePacket, NetError> {
+        self.with_connection(transport::STATUS, None, |connection| {
+            let result = connection.call(STATUS, json!([request.student_number]))?;
+            serde_json::from_value(result).map_err(|e| Failure::Protocol(format!("invalid status: {}", e)))
+        })
+    }
+
+    fn shutdown(&self, _request: &ShutdownRequest) -> Result<CommandResponse, NetError> {
+        // The pool takes the machine offline when the connection closes.
+        self.connection.lock().unwrap().take();
+        Ok(CommandResponse { ok: true, msg: None })
+    }
+}
@@ -0,0 +1,157 @@
+#[cfg(any(test, feature = "test-util"))]
+use std::collections::{HashMap, VecDeque};
+#[cfg(any(test, feature = "test-util"))]
+use std::sync::{Arc, Mutex};
+
+#[cfg(any(test, feature = "test-util"))]
+use crate::pool::Pool;
+
+use super::NetError;
+use super::packets::{
+    BootRequest,
+    CommandResponse,
+    JobRequestPacket,
+    JobResponsePacket,
+    PoolStatusRequestPacket,
+    PoolStatusResponsePacket,
+    ShutdownRequest,
+    SubmittionPacket,
+    SubmittionResponsePacket,
+};
+
+/// Names of the pool operations, as used in the log, in errors and for the
+/// latencies. They are the endpoint paths of the HTTP protocol.
+pub const BOOT: &str = "/boot";
+pub const REQUEST_JOB: &str = "/job/request";
+pub const SUBMIT: &str = "/job/submit";
+pub const STATUS: &str = "/status";
+pub const SHUTDOWN: &str = "/shutdown";
+
+/// A way of talking to the pool. Every operation sends one request and
+/// waits for its answer, retrying is left to the caller.
+pub trait PoolTransport: Send + Sync {
+    /// Announce a machine to the pool.
+    fn boot(&self, request: &BootRequest) -> Result<CommandResponse, NetError>;
+    fn request_job(&self, request: &JobRequestPacket) -> Result<JobResponsePacket, NetError>;
+    fn submit(&self, packet: &SubmittionPacket) -> Result<SubmittionResponsePacket, NetError>;
+    fn status(&self, request: &PoolStatusRequestPacket) -> Result<PoolStatusResponsePacket, NetError>;
+    /// Tell the pool a machine went offline.
+    fn shutdown(&self, request: &ShutdownRequest) -> Result<CommandResponse, NetError>;
+}
+
+/// Calls a pool in the same process directly, without any network. Meant
+/// for checking the mining and shutdown logic. Built for tests and with the
+/// `test-util` feature only.
+#[cfg(any(test, feature = "test-util"))]
+pub struct MemoryTransport {
+    pool: Arc<Pool>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl MemoryTransport {
+    pub fn new(pool: Arc<Pool>) -> Self {
+        MemoryTransport { pool }
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl PoolTransport for MemoryTransport {
+    fn boot(&self, request: &BootRequest) -> Result<CommandResponse, NetError> {
+        Ok(self.pool.boot(request.clone()))
+    }
+
+    fn request_job(&self, request: &JobRequestPacket) -> Result<JobResponsePacket, NetError> {
+        Ok(self.pool.request_job(request.clone()))
+    }
+
+    fn submit(&self, packet: &SubmittionPacket) -> Result<SubmittionResponsePacket, NetError> {
+        Ok(self.pool.submit_job(packet.clone()))
+    }
+
+    fn status(&self, request: &PoolStatusRequestPacket) -> Result<PoolStatusResponsePacket, NetError> {
+        Ok(self.pool.status(request.clone()))
+    }
+
+    fn shutdown(&self, request: &ShutdownRequest) -> Result<CommandResponse, NetError> {
+        Ok(self.pool.shutdown(request.clone()))
+    }
+}
+
+/// A `MemoryTransport` that fails the calls it is told to, for checking how
+/// the client copes with an unreliable pool. Every call is counted, and a
+/// call with a queued failure returns it without reaching the pool.
+#[cfg(any(test, feature = "test-util"))]
+pub struct FaultyTransport {
+    pool: MemoryTransport,
+    failures: Mutex<HashMap<&'static str, VecDeque<NetError>>>,
+    calls: Mutex<HashMap<&'static str, usize>>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl FaultyTransport {
+    pub fn new(pool: Arc<Pool>) -> Self {
+        FaultyTransport {
+            pool: MemoryTransport::new(pool),
+            failures: Mutex::new(HashMap::new()),
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Fail the next call to `operation`, one of the names above, once the
+    /// failures queued before have been returned.
+    pub fn fail(&self, operation: &'static str, error: NetError) {
+        self.failures.lock().unwrap().entry(operation).or_default().push_back(error);
+    }
+
+    /// Fail the next `count` calls to `operation` as if the pool could not
+    /// be reached.
+    pub fn fail_transport(&self, operation: &'static str, count: usize) {
+        for _ in 0..count {
+            self.fail(operation, NetError::Transport {
+                endpoint: String::from(operation),
+                message: String::from("injected failure"),
+            });
+        }
+    }
+
+    /// The number of calls to `operation` so far, failed ones included.
+    pub fn calls(&self, operation: &str) -> usize {
+        self.calls.lock().unwrap().get(operation).copied().unwrap_or(0)
+    }
+
+    fn call(&self, operation: &'static str) -> Result<(), NetError> {
+        *self.calls.lock().unwrap().entry(operation).or_default() += 1;
+        match self.failures.lock().unwrap().get_mut(operation).and_then(VecDeque::pop_front) {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl PoolTransport for FaultyTransport {
+    fn boot(&self, request: &BootRequest) -> Result<CommandResponse, NetError> {
+        self.call(BOOT)?;
+        self.pool.boot(request)
+    }
+
+    fn request_job(&self, request: &JobRequestPacket) -> Result<JobResponsePacket, NetError> {
+        self.call(REQUEST_JOB)?;
+        self.pool.request_job(request)
+    }
+
+    fn submit(&self, packet: &SubmittionPacket) -> Result<SubmittionResponsePacket, NetError> {
+        self.call(SUBMIT)?;
+        self.pool.submit(packet)
+    }
+
+    fn status(&self, request: &PoolStatusRequestPacket) -> Result<PoolStatusResponsePacket, NetError> {
+        self.call(STATUS)?;
+        self.pool.status(request)
+    }
+
+    fn shutdown(&self, request: &ShutdownRequest) -> Result<CommandResponse, NetError> {
+        self.call(SHUTDOWN)?;
+        self.pool.shutdown(request)
+    }
+}
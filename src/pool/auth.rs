@@ -0,0 +1,122 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::net::auth::{self, Proof};
+
+/// The secrets the pool shares with students, see `net::auth`. Students
+/// without one cannot mine, and the secrets are left out of `Debug`.
+#[derive(Clone)]
+pub struct Secrets {
+    secrets: HashMap<String, String>,
+    /// The signatures accepted from each student that are still within the
+    /// clock skew, with their timestamps. A signature seen again is a
+    /// replayed request.
+    seen: Arc<Mutex<HashMap<String, SeenSignatures>>>,
+}
+
+/// Timestamps and signatures.
+type SeenSignatures = HashSet<(u64, String)>;
+
+impl fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secrets({} student(s))", self.secrets.len())
+    }
+}
+
+impl Secrets {
+    /// Read the auth file of the pool, a TOML table of student numbers and
+    /// their secrets:
+    ///
+    /// ```toml
+    /// 12345678 = "the secret of 12345678"
+    /// ```
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let secrets: HashMap<String, String> = toml::from_str(&contents)
+            .map_err(|e| format!("Could not parse {}: {}", path.display(), e))?;
+        if let Some(student_number) = secrets.keys().find(|student_number| !super::is_student_number(student_number)) {
+            return Err(format!("Invalid student number \"{}\" in {}.", student_number, path.display()));
+        }
+        if let Some((student_number, _)) = secrets.iter().find(|(_, secret)| secret.is_empty()) {
+            return Err(format!("The secret of {} in {} is empty.", student_number, path.display()));
+        }
+        Ok(Secrets { secrets, seen: Arc::default() })
+    }
+
+    /// Check the proof of a request in the name of `student_number`. A
+    /// signature is accepted once.
+    pub fn check(&self, student_number: &str, proof: Option<&Proof>, endpoint: &str, body: &[u8]) -> Result<(), String> {
+        self.check_at(student_number, proof, endpoint, body, auth::unix_time())
+    }
+
+    fn check_at(&self, student_number: &str, proof: Option<&Proof>, endpoint: &str, body: &[u8], now: u64) -> Result<(), String> {
+        let secret = self.secrets
+            .get(student_number)
+            .ok_or_else(|| format!("no secret is shared with {}", student_number))?;
+        let proof = proof.ok_or_else(|| String::from("no credentials"))?;
+        proof.verify(secret, endpoint, body, now)?;
+        if let Proof::Signature { timestamp, signature, .. } = proof {
+            let mut seen = self.seen.lock().unwrap();
+            let seen = seen.entry(String::from(student_number)).or_default();
+            // Older signatures are refused for their time anyway.
+            seen.retain(|(timestamp, _)| timestamp + auth::MAX_CLOCK_SKEW >= now);
+            if !seen.insert((*timestamp, signature.clone())) {
+                return Err(String::from("signature already used"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STUDENT_NUMBER: &str = "12345678";
+    const SECRET: &str = "s3cret";
+    const NOW: u64 = 1_700_000_000;
+
+    fn secrets() -> Secrets {
+        let secrets = vec![(String::from(STUDENT_NUMBER), String::from(SECRET))].into_iter().collect();
+        Secrets { secrets, seen: Arc::default() }
+    }
+
+    fn signed(timestamp: u64, nonce: &str, body: &[u8]) -> Proof {
+        Proof::Signature {
+            timestamp,
+            nonce: String::from(nonce),
+            signature: auth::sign(SECRET, timestamp, nonce, "/submit", body),
+        }
+    }
+
+    #[test]
+    fn replayed_signatures_are_refused() {
+        let secrets = secrets();
+        let proof = signed(NOW, "0123456789abcdef", b"{}");
+        assert_eq!(secrets.check_at(STUDENT_NUMBER, Some(&proof), "/submit", b"{}", NOW), Ok(()));
+        assert_eq!(
+            secrets.check_at(STUDENT_NUMBER, Some(&proof), "/submit", b"{}", NOW + 10),
+            Err(String::from("signature already used")),
+        );
+        // The same request signed again has another nonce.
+        let again = signed(NOW, "fedcba9876543210", b"{}");
+        assert_eq!(secrets.check_at(STUDENT_NUMBER, Some(&again), "/submit", b"{}", NOW + 10), Ok(()));
+        // Once out of the window, the clock refuses it.
+        assert!(secrets.check_at(STUDENT_NUMBER, Some(&proof), "/submit", b"{}", NOW + auth::MAX_CLOCK_SKEW + 1).is_err());
+    }
+
+    #[test]
+    fn signatures_out_of_the_window_are_forgotten() {
+        let secrets = secrets();
+        for offset in 0..10 {
+            let proof = signed(NOW + offset, "0123456789abcdef", b"{}");
+            secrets.check_at(STUDENT_NUMBER, Some(&proof), "/submit", b"{}", NOW + offset).unwrap();
+        }
+        let proof = signed(NOW + 400, "0123456789abcdef", b"{}");
+        secrets.check_at(STUDENT_NUMBER, Some(&proof), "/submit", b"{}", NOW + 400).unwrap();
+        assert_eq!(secrets.seen.lock().unwrap()[STUDENT_NUMBER].len(), 1);
+    }
+}
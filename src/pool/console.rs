@@ -0,0 +1,47 @@
+use std::io::{self, BufRead};
+use std::sync::Arc;
+use std::thread;
+
+use crate::net::packets::check_difficulty;
+
+use super::Pool;
+
+/// Start the thread reading operator commands from stdin, one per line:
+///
+/// * `difficulty <bits>` issues the jobs from now on at another difficulty,
+/// * `broadcast <message>` sends a message to every subscribed miner.
+///
+/// The thread ends with stdin, so a pool started without a terminal simply
+/// has no console.
+pub fn begin(pool: Arc<Pool>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => return,
+            };
+            if let Err(message) = run(&pool, line.trim()) {
+                println!("{}", message);
+            }
+        }
+    })
+}
+
+fn run(pool: &Pool, line: &str) -> Result<(), String> {
+    let (command, argument) = match line.find(' ') {
+        Some(space) => (&line[..space], line[space + 1..].trim()),
+        None => (line, ""),
+    };
+    match command {
+        "" => {}
+        "difficulty" => {
+            let difficulty = argument.parse::<u8>()
+                .map_err(|e| format!("Invalid difficulty \"{}\": {}", argument, e))?;
+            check_difficulty(difficulty)?;
+            pool.set_difficulty(difficulty);
+        }
+        "broadcast" if !argument.is_empty() => pool.broadcast(argument),
+        _ => return Err(String::from("Commands: difficulty <bits>, broadcast <message>.")),
+    }
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::net::auth::Proof;
+
+use super::{console, push, stratum, Pool};
+
+/// Serve the pool over HTTP/JSON on the configured address. Requests are
+/// answered by `workers` threads, push connections at `/push` get a thread
+/// each. Also starts the stratum server when configured, the console on
+/// stdin and a thread expiring stale jobs. Blocks for as long as the server
+/// runs.
+pub fn serve(pool: Arc<Pool>) -> Result<(), String> {
+    let server = Server::http(&pool.config().bind)
+        .map_err(|e| format!("Could not listen on {}: {}", pool.config().bind, e))?;
+    let server = Arc::new(server);
+    println!("Pool listening on http://{}", pool.config().bind);
+    if pool.config().secrets.is_some() {
+        println!("Requests must prove the student they come from.");
+    }
+
+    if let Some(bind) = &pool.config().stratum_bind {
+        stratum::begin(Arc::clone(&pool), bind)?;
+        println!("Pool listening on {}://{}", crate::net::stratum::SCHEME, bind);
+    }
+    console::begin(Arc::clone(&pool));
+    if pool.config().job_ttl.is_some() {
+        let pool = Arc::clone(&pool);
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(1));
+            pool.expire_stale_jobs();
+        });
+    }
+
+    let workers: Vec<_> = (0..pool.config().workers.max(1))
+        .map(|_| {
+            let server = Arc::clone(&server);
+            let pool = Arc::clone(&pool);
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    if *request.method() == Method::Get && request.url() == "/push" {
+                        let pool = Arc::clone(&pool);
+                        thread::spawn(move || push::serve(&pool, request));
+                    } else {
+                        handle(&pool, request);
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().map_err(|_| String::from("Pool worker thread panicked."))?;
+    }
+    Ok(())
+}
+
+fn handle(pool: &Pool, mut request: Request) {
+    if *request.method() != Method::Post {
+        let _ = request.respond(Response::empty(405));
+        return;
+    }
+    let mut body = String::new();
+    if request.as_reader().read_to_string(&mut body).is_err() {
+        let _ = request.respond(Response::empty(400));
+        return;
+    }
+    if let Err(reason) = authenticate(pool, &request, &body) {
+        println!("Refused {}: {}", request.url(), reason);
+        let _ = request.respond(Response::empty(401));
+        return;
+    }
+
+    let response = match request.url() {
+        "/boot" => route(&body, |packet| pool.boot(packet)),
+        "/shutdown" => route(&body, |packet| pool.shutdown(packet)),
+        "/job/request" => route(&body, |packet| pool.request_job(packet)),
+        "/job/submit" => route(&body, |packet| pool.submit_job(packet)),
+        "/status" => route(&body, |packet| pool.status(packet)),
+        _ => Err(404),
+    };
+
+    let _ = match response {
+        Ok(json) => {
+            let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                .expect("Static header is valid.");
+            request.respond(Response::from_string(json).with_header(header))
+        }
+        Err(status_code) => request.respond(Response::empty(status_code)),
+    };
+}
+
+/// Check the proof of a request acting in a student's name. Status
+/// requests only read and need none.
+fn authenticate(pool: &Pool, request: &Request, body: &str) -> Result<(), String> {
+    if pool.config().secrets.is_none() || request.url() == "/status" {
+        return Ok(());
+    }
+    #[derive(Deserialize)]
+    struct Student {
+        student_number: String,
+    }
+    let student = serde_json::from_str::<Student>(body).map_err(|e| format!("invalid request: {}", e))?;
+    let proof = Proof::from_headers(|name| header(request, name))?;
+    pool.authenticate(&student.student_number, proof.as_ref(), request.url(), body.as_bytes())
+}
+
+/// The value of a request header, the name compared ignoring case.
+pub fn header(request: &Request, name: &str) -> Option<String> {
+    request.headers()
+        .iter()
+        .find(|header| header.field.as_str().as_str().eq_ignore_ascii_case(name))
+        .map(|header| header.value.to_string())
+}
+
+/// Decode the request packet, run the handler and encode its response.
+fn route<T, U>(body: &str, handler: impl FnOnce(T) -> U) -> Result<String, u16>
+where T: DeserializeOwned, U: Serialize
+{
+    let packet = serde_json::from_str::<T>(body).map_err(|_| 400u16)?;
+    serde_json::to_string(&handler(packet)).map_err(|_| 500u16)
+}
@@ -0,0 +1,510 @@
+pub mod auth;
+pub mod console;
+pub mod http;
+pub mod push;
+pub mod stratum;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use structopt::StructOpt;
+
+use crate::net::auth::Proof;
+use crate::net::packets::{
+    BootRequest,
+    CommandResponse,
+    Job,
+    JobRequestPacket,
+    JobResponsePacket,
+    PoolStatusRequestPacket,
+    PoolStatusResponsePacket,
+    PushMessage,
+    ShutdownRequest,
+    Solution,
+    SubmittionPacket,
+    SubmittionResponsePacket,
+    check_difficulty,
+};
+use crate::verify::{nounce_offset, verify_solution};
+
+/// Settings for a locally hosted pool.
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Address the pool listens on, e.g. 127.0.0.1:9876
+    pub bind: String,
+    /// Address to accept stratum connections on as well, if any.
+    pub stratum_bind: Option<String>,
+    /// Number of nonces handed out with each job.
+    pub job_size: u64,
+    /// Difficulty of the issued jobs, solutions with fewer leading zero bits
+    /// are refused.
+    pub min_zero_bits: u8,
+    /// Number of threads answering requests.
+    pub workers: usize,
+    /// Seconds before an unsubmitted job goes stale and its range is
+    /// handed out again. `None` keeps jobs forever.
+    pub job_ttl: Option<u64>,
+    /// Secrets shared with the students. When set, requests acting in a
+    /// student's name must prove they come from them.
+    pub secrets: Option<auth::Secrets>,
+}
+
+/// Command line flags of a locally hosted pool, shared by `hasher pool` and
+/// the `hasher-pool` binary.
+#[derive(StructOpt, Debug)]
+pub struct PoolArgs {
+    /// Address to listen on.
+    #[structopt(long, default_value = "127.0.0.1:9876")]
+    pub bind: String,
+    /// Also speak the stratum protocol on this address, e.g.
+    /// 127.0.0.1:3333
+    #[structopt(long)]
+    pub stratum_bind: Option<String>,
+    /// Number of nonces in each job.
+    #[structopt(long, default_value = "100000000")]
+    pub job_size: u64,
+    /// Minimum number of leading zero bits for a solution to be accepted.
+    #[structopt(long, default_value = "34")]
+    pub min_zero_bits: u8,
+    /// Number of threads answering requests.
+    #[structopt(long, default_value = "4")]
+    pub workers: usize,
+    /// Seconds a miner has to submit a job before its range is handed to
+    /// someone else. 0 keeps jobs reserved forever.
+    #[structopt(long, default_value = "600")]
+    pub job_ttl: u64,
+    /// TOML file of student numbers and the secrets shared with them, e.g.
+    /// `12345678 = "secret"`. Requests must then be signed or carry the
+    /// secret as a token, except status requests.
+    #[structopt(long, parse(from_os_str))]
+    pub auth_file: Option<PathBuf>,
+}
+
+impl PoolArgs {
+    pub fn config(&self) -> Result<PoolConfig, String> {
+        if self.job_size == 0 {
+            return Err(String::from("The job size must be greater than zero."));
+        }
+        check_difficulty(self.min_zero_bits)?;
+        Ok(PoolConfig {
+            bind: self.bind.clone(),
+            stratum_bind: self.stratum_bind.clone(),
+            job_size: self.job_size,
+            min_zero_bits: self.min_zero_bits,
+            workers: self.workers,
+            job_ttl: match self.job_ttl {
+                0 => None,
+                seconds => Some(seconds),
+            },
+            secrets: self.auth_file.as_deref().map(auth::Secrets::load).transpose()?,
+        })
+    }
+}
+
+/// A job that has been handed out but not yet submitted.
+struct IssuedJob {
+    job: Job,
+    student_number: String,
+    name: String,
+    issued_at: Instant,
+}
+
+#[derive(Default)]
+struct MachineStats {
+    online: bool,
+    shares: usize,
+    hash_rate: f64,
+}
+
+#[derive(Default)]
+struct StudentStats {
+    shares: usize,
+    machines: HashMap<String, MachineStats>,
+}
+
+impl StudentStats {
+    fn machine(&mut self, name: &str) -> &mut MachineStats {
+        self.machines.entry(String::from(name)).or_default()
+    }
+
+    /// The sum of the latest reported rate of every online machine.
+    fn hash_rate(&self) -> f64 {
+        self.machines
+            .values()
+            .filter(|machine| machine.online)
+            .map(|machine| machine.hash_rate)
+            .sum()
+    }
+}
+
+/// A machine listening on the push connection.
+struct Subscriber {
+    student_number: String,
+    name: String,
+    sender: Sender<PushMessage>,
+}
+
+struct PoolState {
+    /// Difficulty of the jobs issued from now on, starts at the configured
+    /// one and is changed from the console.
+    difficulty: u8,
+    next_job_number: u64,
+    next_nounce: u64,
+    issued: HashMap<u64, IssuedJob>,
+    /// The unhashed rest of partially submitted jobs, handed out before new
+    /// ranges.
+    reissue: VecDeque<Job>,
+    completed_jobs: u64,
+    total_shares: usize,
+    best_zero_length: u8,
+    students: HashMap<String, StudentStats>,
+    subscribers: Vec<Subscriber>,
+}
+
+impl PoolState {
+    /// Send a message to the subscribers the filter picks. Subscribers that
+    /// hung up are dropped.
+    fn push(&mut self, to: impl Fn(&Subscriber) -> bool, message: impl Fn(&Subscriber) -> PushMessage) {
+        self.subscribers.retain(|subscriber| {
+            !to(subscriber) || subscriber.sender.send(message(subscriber)).is_ok()
+        });
+    }
+
+    fn status(&self, student_number: &str) -> PoolStatusResponsePacket {
+        let (user_total_hash_rate, user_total_shares) = self.students
+            .get(student_number)
+            .map(|student| (student.hash_rate(), student.shares))
+            .unwrap_or((0.0, 0));
+        PoolStatusResponsePacket {
+            user_total_hash_rate,
+            user_total_shares,
+            pool_total_shares: self.total_shares,
+            pool_best_zero_length: self.best_zero_length,
+            completed_jobs: self.completed_jobs,
+        }
+    }
+}
+
+/// The pool bookkeeping. Each request handler takes the decoded packet and
+/// returns the response packet, leaving the transport to the caller.
+pub struct Pool {
+    config: PoolConfig,
+    state: Mutex<PoolState>,
+}
+
+impl Pool {
+    pub fn new(config: PoolConfig) -> Self {
+        Pool {
+            state: Mutex::new(PoolState {
+                difficulty: config.min_zero_bits,
+                next_job_number: 1,
+                next_nounce: 0,
+                issued: HashMap::new(),
+                reissue: VecDeque::new(),
+                completed_jobs: 0,
+                total_shares: 0,
+                best_zero_length: 0,
+                students: HashMap::new(),
+                subscribers: vec![],
+            }),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &PoolConfig {
+        &self.config
+    }
+
+    /// Check that a request in the name of `student_number` comes from
+    /// them. Every request passes when the pool has no secrets.
+    pub fn authenticate(&self, student_number: &str, proof: Option<&Proof>, endpoint: &str, body: &[u8]) -> Result<(), String> {
+        match &self.config.secrets {
+            Some(secrets) => secrets.check(student_number, proof, endpoint, body),
+            None => Ok(()),
+        }
+    }
+
+    pub fn boot(&self, request: BootRequest) -> CommandResponse {
+        let mut state = self.state.lock().unwrap();
+        let student = state.students.entry(request.student_number.clone()).or_default();
+        student.machine(&request.name).online = true;
+        println!("Machine \"{}\" of {} connected.", request.name, request.student_number);
+        CommandResponse { ok: true, msg: None }
+    }
+
+    pub fn shutdown(&self, request: ShutdownRequest) -> CommandResponse {
+        let mut state = self.state.lock().unwrap();
+        if let Some(student) = state.students.get_mut(&request.student_number) {
+            let machine = student.machine(&request.name);
+            machine.online = false;
+            machine.hash_rate = 0.0;
+        }
+        println!("Machine \"{}\" of {} disconnected.", request.name, request.student_number);
+        CommandResponse { ok: true, msg: None }
+    }
+
+    pub fn request_job(&self, request: JobRequestPacket) -> JobResponsePacket {
+        if !is_student_number(&request.student_number) {
+            return JobResponsePacket::Error(String::from("Invalid student number."));
+        }
+        let mut state = self.state.lock().unwrap();
+        self.expire_jobs(&mut state);
+        match self.issue_job(&mut state, request.student_number, request.name) {
+            Ok(job) => JobResponsePacket::Success(job),
+            Err(message) => JobResponsePacket::Error(message),
+        }
+    }
+
+    /// Hand the next range to a machine: the rest of a partial or stale job
+    /// if there is one, a new range otherwise.
+    fn issue_job(&self, state: &mut PoolState, student_number: String, name: String) -> Result<Job, String> {
+        let job = match state.reissue.pop_front() {
+            Some(rest) => Job {
+                number: state.next_job_number,
+                ..rest
+            },
+            None => {
+                let nounce_start = state.next_nounce;
+                let nounce_end = nounce_start.checked_add(self.config.job_size)
+                    .ok_or_else(|| String::from("Nonce space exhausted."))?;
+                state.next_nounce = nounce_end;
+                Job {
+                    number: state.next_job_number,
+                    size: self.config.job_size,
+                    nounce_start,
+                    nounce_end,
+                    difficulty: state.difficulty,
+                    offset: 0,
+                    ttl: self.config.job_ttl,
+                }
+            }
+        };
+        state.next_job_number += 1;
+        state.issued.insert(job.number, IssuedJob {
+            job,
+            student_number,
+            name,
+            issued_at: Instant::now(),
+        });
+        Ok(job)
+    }
+
+    pub fn submit_job(&self, packet: SubmittionPacket) -> SubmittionResponsePacket {
+        let mut state = self.state.lock().unwrap();
+
+        // Only the machine the job was handed to may submit it.
+        let job = match state.issued.get(&packet.job_n) {
+            Some(issued)
+                if issued.student_number == packet.student_number
+                    && issued.name == packet.name => issued.job,
+            _ => return reject(&packet, String::from("unknown or stale job")),
+        };
+        if packet.nounce_start != job.nounce_start
+            || packet.offset != job.offset
+            || packet.nounce_end < job.nounce_start + job.offset
+            || packet.nounce_end > job.nounce_end
+        {
+            return reject(&packet, String::from("nonce range does not match the job"));
+        }
+        if packet.partial != (packet.nounce_end < job.nounce_end) {
+            return reject(&packet, String::from("partial flag does not match the nonce range"));
+        }
+
+        let mut best_zero_length = 0;
+        let mut seen = HashSet::new();
+        for solution in packet.solutions.iter() {
+            let valid = if seen.insert(&solution.nounce) {
+                self.check_solution(&packet, solution, job.difficulty)
+            } else {
+                Err(String::from("duplicate solution"))
+            };
+            match valid {
+                Ok(zero_length) => best_zero_length = best_zero_length.max(zero_length),
+                Err(reason) => {
+                    return reject(&packet, format!("solution {}: {}", solution.nounce, reason));
+                }
+            }
+        }
+
+        // Everything checks out, record the shares. The rest of a partial
+        // job goes back to be handed out again.
+        state.issued.remove(&packet.job_n);
+        if packet.partial {
+            state.reissue.push_back(Job {
+                offset: packet.nounce_end - job.nounce_start,
+                ..job
+            });
+        } else {
+            state.completed_jobs += 1;
+        }
+        state.total_shares += packet.solutions.len();
+        state.best_zero_length = state.best_zero_length.max(best_zero_length);
+        let student = state.students.entry(packet.student_number.clone()).or_default();
+        student.shares += packet.solutions.len();
+        let machine = student.machine(&packet.name);
+        machine.online = true;
+        machine.shares += packet.solutions.len();
+        machine.hash_rate = packet.total_hashes_per_second;
+        self.push_after_submission(&mut state, &packet);
+        println!(
+            "Accepted {}job {} from \"{}\" of {} with {} solution(s).",
+            if packet.partial { "partial " } else { "" },
+            packet.job_n,
+            packet.name,
+            packet.student_number,
+            packet.solutions.len()
+        );
+        SubmittionResponsePacket::Accepted
+    }
+
+    pub fn status(&self, request: PoolStatusRequestPacket) -> PoolStatusResponsePacket {
+        self.state.lock().unwrap().status(&request.student_number)
+    }
+
+    /// Register a machine for push messages. The messages arrive on the
+    /// returned receiver until it is dropped.
+    pub fn subscribe(&self, request: BootRequest) -> Receiver<PushMessage> {
+        let (sender, receiver) = mpsc::channel();
+        let mut state = self.state.lock().unwrap();
+        let _ = sender.send(PushMessage::Status(state.status(&request.student_number)));
+        println!("Machine \"{}\" of {} subscribed to push messages.", request.name, request.student_number);
+        state.subscribers.push(Subscriber {
+            student_number: request.student_number,
+            name: request.name,
+            sender,
+        });
+        receiver
+    }
+
+    /// Issue the jobs from now on at another difficulty and tell every
+    /// subscriber.
+    pub fn set_difficulty(&self, difficulty: u8) {
+        let mut state = self.state.lock().unwrap();
+        state.difficulty = difficulty;
+        state.push(|_| true, |_| PushMessage::Difficulty(difficulty));
+        println!("New jobs are issued at difficulty {}.", difficulty);
+    }
+
+    /// Send a message to every subscriber.
+    pub fn broadcast(&self, message: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.push(|_| true, |_| PushMessage::Broadcast(String::from(message)));
+        println!("Broadcast to {} subscriber(s).", state.subscribers.len());
+    }
+
+    /// Hand out the ranges of stale jobs again, without waiting for the next
+    /// job request, so their owners hear about it right away.
+    pub fn expire_stale_jobs(&self) {
+        let mut state = self.state.lock().unwrap();
+        self.expire_jobs(&mut state);
+    }
+
+    /// Tell every subscriber their new statistics, and give a subscribed
+    /// machine that finished its job the next one. A partial submission
+    /// means the machine is stopping a thread, it gets nothing.
+    fn push_after_submission(&self, state: &mut PoolState, packet: &SubmittionPacket) {
+        let statuses: HashMap<String, PoolStatusResponsePacket> = state.subscribers
+            .iter()
+            .map(|subscriber| (subscriber.student_number.clone(), state.status(&subscriber.student_number)))
+            .collect();
+        state.push(|_| true, |subscriber| PushMessage::Status(statuses[&subscriber.student_number]));
+
+        let is_sender = |subscriber: &Subscriber| {
+            subscriber.student_number == packet.student_number && subscriber.name == packet.name
+        };
+        if packet.partial || !state.subscribers.iter().any(is_sender) {
+            return;
+        }
+        match self.issue_job(state, packet.student_number.clone(), packet.name.clone()) {
+            Ok(job) => state.push(is_sender, |_| PushMessage::Job(job)),
+            Err(message) => println!("Could not push a job to \"{}\": {}", packet.name, message),
+        }
+    }
+
+    /// Hand out the ranges of stale jobs again, before any new range.
+    fn expire_jobs(&self, state: &mut PoolState) {
+        let ttl = match self.config.job_ttl {
+            Some(ttl) => Duration::from_secs(ttl),
+            None => return,
+        };
+        let stale: Vec<u64> = state.issued
+            .iter()
+            .filter(|(_, issued)| issued.issued_at.elapsed() >= ttl)
+            .map(|(&number, _)| number)
+            .collect();
+        for number in stale {
+            if let Some(issued) = state.issued.remove(&number) {
+                println!("Job {} of {} went stale, handing out its range again.", number, issued.student_number);
+                state.push(
+                    |subscriber| subscriber.student_number == issued.student_number && subscriber.name == issued.name,
+                    |_| PushMessage::Stale(number),
+                );
+                state.reissue.push_front(issued.job);
+            }
+        }
+    }
+
+    /// Check a submitted solution is valid and inside the submitted range.
+    /// Returns the number of leading zero bits of a valid solution.
+    fn check_solution(&self, packet: &SubmittionPacket, solution: &Solution, difficulty: u8) -> Result<u8, String> {
+        let zero_length = verify_solution(&packet.student_number, solution, difficulty)
+            .map_err(|e| e.to_string())?;
+        match nounce_offset(packet.nounce_start, &solution.nounce) {
+            Some(offset) if offset >= packet.offset && offset < packet.nounce_end - packet.nounce_start => {}
+            _ => return Err(String::from("nonce outside of the submitted range")),
+        }
+        Ok(zero_length)
+    }
+}
+
+fn reject(packet: &SubmittionPacket, reason: String) -> SubmittionResponsePacket {
+    println!("Rejected job {} from {}: {}.", packet.job_n, packet.student_number, reason);
+    if packet.explain_rejections {
+        SubmittionResponsePacket::RejectedWithReason(reason)
+    } else {
+        SubmittionResponsePacket::Rejected
+    }
+}
+
+fn is_student_number(student_number: &str) -> bool {
+    student_number.len() == 8 && student_number.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool() -> Pool {
+        Pool::new(PoolConfig {
+            bind: String::from("127.0.0.1:0"),
+            stratum_bind: None,
+            job_size: 1000,
+            min_zero_bits: 1,
+            workers: 1,
+            job_ttl: None,
+            secrets: None,
+        })
+    }
+
+    #[test]
+    fn old_clients_are_sent_plain_rejections() {
+        let pool = pool();
+        // A client from before explain_rejections, submitting a job it was never given.
+        let packet: SubmittionPacket = serde_json::from_str(r#"{
+            "job_n": 7, "name": "old", "student_number": "12345678",
+            "thread_hashes_per_second": 0.0, "total_hashes_per_second": 0.0,
+            "nounce_start": 0, "nounce_end": 10, "solutions": []
+        }"#).unwrap();
+        let response = serde_json::to_string(&pool.submit_job(packet.clone())).unwrap();
+        assert_eq!(response, r#""Rejected""#);
+
+        let packet = SubmittionPacket { explain_rejections: true, ..packet };
+        match pool.submit_job(packet) {
+            SubmittionResponsePacket::RejectedWithReason(reason) => assert_eq!(reason, "unknown or stale job"),
+            response => panic!("expected a reason, got {:?}", response),
+        }
+    }
+}
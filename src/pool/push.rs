@@ -0,0 +1,86 @@
+use std::io;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+
+use tiny_http::{Header, Request, Response};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+use crate::net::auth::{self, Proof};
+use crate::net::packets::BootRequest;
+
+use super::Pool;
+use super::http::header;
+
+/// How often an idle push connection is pinged, so miners can tell a quiet
+/// pool from a dead connection.
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Serve a push connection: upgrade the request to a WebSocket, read the
+/// subscription and forward the pool's messages until the miner hangs up.
+/// The proof of the subscription comes with the upgrade request. Blocks for
+/// as long as the connection lasts.
+pub fn serve(pool: &Pool, request: Request) {
+    let key = match header(&request, "Sec-WebSocket-Key") {
+        Some(key) => derive_accept_key(key.as_bytes()),
+        None => {
+            let _ = request.respond(Response::empty(400));
+            return;
+        }
+    };
+    let proof = match Proof::from_headers(|name| header(&request, name)) {
+        Ok(proof) => proof,
+        Err(message) => {
+            println!("Refused a push connection: {}", message);
+            let _ = request.respond(Response::empty(401));
+            return;
+        }
+    };
+    let accept = Header::from_bytes(&b"Sec-WebSocket-Accept"[..], key.as_bytes())
+        .expect("The accept key is base64.");
+    let stream = request.upgrade("websocket", Response::empty(101).with_header(accept));
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+
+    let subscription = read_subscription(&mut socket).and_then(|(subscription, text)| {
+        pool.authenticate(&subscription.student_number, proof.as_ref(), auth::PUSH_ENDPOINT, text.as_bytes())?;
+        Ok(subscription)
+    });
+    let subscription = match subscription {
+        Ok(subscription) => subscription,
+        Err(message) => {
+            println!("Refused a push connection: {}", message);
+            let _ = socket.close(None);
+            return;
+        }
+    };
+    let messages = pool.subscribe(subscription);
+    loop {
+        let message = match messages.recv_timeout(PING_INTERVAL) {
+            Ok(message) => Message::Text(serde_json::to_string(&message).expect("Push messages encode.")),
+            Err(RecvTimeoutError::Timeout) => Message::Ping(vec![]),
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        // Dropping the receiver unsubscribes the miner.
+        if socket.write_message(message).is_err() {
+            return;
+        }
+    }
+}
+
+/// The first message names the machine. Returns it with its text, which
+/// is what the proof is made over.
+fn read_subscription(socket: &mut WebSocket<Box<dyn tiny_http::ReadWrite + Send>>) -> Result<(BootRequest, String), String> {
+    loop {
+        match socket.read_message() {
+            Ok(Message::Text(text)) => {
+                let subscription = serde_json::from_str(&text).map_err(|e| format!("invalid subscription: {}", e))?;
+                return Ok((subscription, text));
+            }
+            Ok(Message::Ping(_)) | Ok(Message::Pong(_)) => continue,
+            Ok(_) => return Err(String::from("expected a subscription")),
+            Err(tungstenite::Error::Io(e)) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
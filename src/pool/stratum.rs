@@ -0,0 +1,212 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::net::auth::Proof;
+use crate::net::packets::{
+    BootRequest,
+    Job,
+    JobRequestPacket,
+    JobResponsePacket,
+    PoolStatusRequestPacket,
+    PushMessage,
+    ShutdownRequest,
+    SubmittionResponsePacket,
+};
+use crate::net::stratum::{self, REFUSED};
+
+use super::Pool;
+
+/// How long a read waits before the pool's own messages are forwarded.
+const READ_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Accept stratum connections on `bind`, see `net::stratum` for the
+/// protocol. Each connection gets a thread.
+pub fn begin(pool: Arc<Pool>, bind: &str) -> Result<thread::JoinHandle<()>, String> {
+    let listener = TcpListener::bind(bind).map_err(|e| format!("Could not listen on {}: {}", bind, e))?;
+    let sessions = Arc::new(AtomicU64::new(1));
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let pool = Arc::clone(&pool);
+            let session = sessions.fetch_add(1, Ordering::Relaxed);
+            thread::spawn(move || {
+                if let Err(e) = Session::new(&pool, session).serve(stream) {
+                    println!("Stratum session {} failed: {}", session, e);
+                }
+            });
+        }
+    }))
+}
+
+struct Session<'a> {
+    pool: &'a Pool,
+    id: u64,
+    /// The machine the connection is authorized as.
+    worker: Option<BootRequest>,
+    /// The pool's messages for the machine.
+    messages: Option<Receiver<PushMessage>>,
+    /// The difficulty the miner was last told.
+    difficulty: Option<u8>,
+}
+
+impl<'a> Session<'a> {
+    fn new(pool: &'a Pool, id: u64) -> Self {
+        Session { pool, id, worker: None, messages: None, difficulty: None }
+    }
+
+    /// Answer requests and forward the pool's messages until the miner hangs
+    /// up, then take its machine offline.
+    fn serve(mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_INTERVAL))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        let mut line = String::new();
+        let result = loop {
+            // A line cut by the timeout stays in `line` and is completed by
+            // the next read.
+            match reader.read_line(&mut line) {
+                Ok(0) => break Ok(()),
+                Ok(_) => {
+                    let answer = self.handle(&line);
+                    line.clear();
+                    if let Err(e) = self.write(&mut writer, &answer) {
+                        break Err(e);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {}
+                Err(e) => break Err(e),
+            }
+            if let Err(e) = self.forward(&mut writer) {
+                break Err(e);
+            }
+        };
+        if let Some(worker) = self.worker.take() {
+            self.pool.shutdown(ShutdownRequest { name: worker.name, student_number: worker.student_number });
+        }
+        result
+    }
+
+    /// Answer a request. Returns the messages to send, notifications first.
+    fn handle(&mut self, line: &str) -> Vec<Value> {
+        let mut request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => return vec![error(Value::Null, &format!("invalid request: {}", e))],
+        };
+        let id = request["id"].take();
+        let params = request["params"].take();
+        let method = request["method"].as_str().unwrap_or_default();
+        let mut messages = vec![];
+        let result = match method {
+            stratum::SUBSCRIBE => Ok(json!([[[stratum::NOTIFY, self.id.to_string()]], "", 0])),
+            stratum::AUTHORIZE => self.authorize(params),
+            stratum::REQUEST_JOB => self.request_job().map(|job| {
+                messages.extend(job);
+                Value::Bool(true)
+            }),
+            stratum::SUBMIT => self.submit(params),
+            stratum::STATUS => serde_json::from_value::<(String,)>(params)
+                .map(|(student_number,)| json!(self.pool.status(PoolStatusRequestPacket { student_number })))
+                .map_err(|e| format!("invalid {}: {}", stratum::STATUS, e)),
+            _ => Err(format!("unknown method \"{}\"", method)),
+        };
+        messages.push(match result {
+            Ok(result) => json!({ "id": id, "result": result, "error": null }),
+            Err(message) => error(id, &message),
+        });
+        messages
+    }
+
+    fn authorize(&mut self, params: Value) -> Result<Value, String> {
+        let (worker, password) = serde_json::from_value::<(String, String)>(params)
+            .map_err(|e| format!("invalid {}: {}", stratum::AUTHORIZE, e))?;
+        let (student_number, name) = stratum::parse_worker_name(&worker)
+            .filter(|(student_number, _)| super::is_student_number(student_number))
+            .ok_or_else(|| format!("invalid worker name \"{}\", expected <student number>.<machine name>", worker))?;
+        let proof = Proof::from_password(&password)?;
+        self.pool.authenticate(&student_number, proof.as_ref(), stratum::AUTHORIZE, worker.as_bytes())
+            .map_err(|reason| format!("worker \"{}\" refused: {}", worker, reason))?;
+        let request = BootRequest { student_number, name };
+        self.pool.boot(request.clone());
+        self.messages = Some(self.pool.subscribe(request.clone()));
+        self.worker = Some(request);
+        Ok(Value::Bool(true))
+    }
+
+    /// Issue a job. Returns its notifications.
+    fn request_job(&mut self) -> Result<Vec<Value>, String> {
+        let worker = self.worker.as_ref().ok_or_else(|| String::from("not authorized"))?;
+        match self.pool.request_job(JobRequestPacket {
+            student_number: worker.student_number.clone(),
+            name: worker.name.clone(),
+        }) {
+            JobResponsePacket::Success(job) => Ok(self.notify(&job)),
+            JobResponsePacket::Error(message) => Err(message),
+        }
+    }
+
+    fn submit(&mut self, params: Value) -> Result<Value, String> {
+        let packet = stratum::packet_from_submit(params)?;
+        match &self.worker {
+            Some(worker) if worker.student_number == packet.student_number && worker.name == packet.name => {}
+            _ => return Err(String::from("not authorized as this worker")),
+        }
+        match self.pool.submit_job(packet) {
+            SubmittionResponsePacket::Accepted => Ok(Value::Bool(true)),
+            SubmittionResponsePacket::Rejected => Err(String::from("rejected")),
+            SubmittionResponsePacket::RejectedWithReason(reason) => Err(reason),
+        }
+    }
+
+    /// The notifications handing a job to the miner, telling it the
+    /// difficulty first when it changed.
+    fn notify(&mut self, job: &Job) -> Vec<Value> {
+        let mut messages = vec![];
+        if self.difficulty != Some(job.difficulty) {
+            self.difficulty = Some(job.difficulty);
+            messages.push(notification(stratum::SET_DIFFICULTY, json!([job.difficulty])));
+        }
+        messages.push(notification(stratum::NOTIFY, stratum::notify_params(job)));
+        messages
+    }
+
+    /// Send the jobs the pool pushed for the machine. The other push
+    /// messages have no stratum counterpart.
+    fn forward(&mut self, writer: &mut TcpStream) -> io::Result<()> {
+        let jobs: Vec<_> = match &self.messages {
+            Some(messages) => messages
+                .try_iter()
+                .filter_map(|message| match message {
+                    PushMessage::Job(job) => Some(job),
+                    _ => None,
+                })
+                .collect(),
+            None => return Ok(()),
+        };
+        for job in jobs {
+            let messages = self.notify(&job);
+            self.write(writer, &messages)?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, writer: &mut TcpStream, messages: &[Value]) -> io::Result<()> {
+        for message in messages {
+            writer.write_all(format!("{}\n", message).as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "id": null, "method": method, "params": params })
+}
+
+fn error(id: Value, message: &str) -> Value {
+    json!({ "id": id, "result": null, "error": [REFUSED, message, null] })
+}
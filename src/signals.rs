@@ -0,0 +1,49 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[cfg(unix)]
+use signal_hook::consts::SIGHUP;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::flag;
+
+use crate::application::{App, Application};
+
+/// Start the thread that turns signals into application events. SIGINT and
+/// SIGTERM start the same shutdown as the Q key, a second one exits right
+/// away. SIGHUP calls `reload` to apply the configuration again.
+pub fn begin<F>(mut app: App, reload: F) -> io::Result<thread::JoinHandle<()>>
+where F: Fn(&mut Application) + Send + 'static
+{
+    let stop = Arc::new(AtomicBool::new(false));
+    for &signal in [SIGINT, SIGTERM].iter() {
+        flag::register_conditional_shutdown(signal, 1, Arc::clone(&stop))?;
+        flag::register(signal, Arc::clone(&stop))?;
+    }
+    let hangup = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    flag::register(SIGHUP, Arc::clone(&hangup))?;
+
+    Ok(thread::spawn(move || loop {
+        if stop.load(Ordering::Relaxed) {
+            app.lock(|app| {
+                if !app.quitting {
+                    app.quitting = true;
+                    app.log.info("Stopping, waiting for the mining threads to finish.");
+                }
+            });
+        }
+        if hangup.swap(false, Ordering::Relaxed) {
+            app.lock(|app| {
+                app.log.info("Reloading the configuration.");
+                reload(app);
+            });
+        }
+        if app.lock(|app| app.quitting) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }))
+}
@@ -8,13 +8,15 @@ pub fn begin(mut app: App) -> std::thread::JoinHandle<()> {
     thread::spawn(move || {
         thread::sleep(Duration::from_millis(5000));
         'status_loop : loop {
-            // Fetch the pool status
-            let pool_status_result= pool_status(App::clone(&app));
-            if let Ok(pool_status) = pool_status_result {
-                app.lock(|app| {
-                    app.pool_status = Some(pool_status);
-                });
-        }
+            // Fetch the pool status, unless the pool pushes it.
+            if !app.lock(|app| app.push_connected) {
+                let pool_status_result= pool_status(App::clone(&app));
+                if let Ok(pool_status) = pool_status_result {
+                    app.lock(|app| {
+                        app.pool_status = Some(pool_status);
+                    });
+                }
+            }
 
             // Sleep for 5 seconds
             for _ in 0..50 {
@@ -6,7 +6,7 @@ use std::error::Error;
 //     raw::IntoRawMode,
 //     screen::AlternateScreen};
 use crossterm::{
-        event::{self, DisableMouseCapture, EnableMouseCapture, Event as CEvent, KeyCode},
+        event::{DisableMouseCapture, EnableMouseCapture},
         execute,
         terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     };
@@ -22,11 +22,11 @@ use tui::{
     Frame,
 };
 
-use crate::util::event::Event;
-use crate::util::event::Events;
+use crate::util::event::{self, Event, Events};
 
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 use crate::application::{
     App,
     Application,
@@ -34,11 +34,26 @@ use crate::application::{
     ThreadState,
 };
 
+use crate::config::{key_name, KeyBindings, Theme, MAX_THREADS};
 use crate::log::LogMessage;
+use crate::dispatcher::JobBuffer;
+use crate::hashing::BackendKind;
 
-pub fn main_loop(app: Arc<Mutex<Application>>) -> Result<(), Box<dyn Error>> {
+/// Puts the terminal back into its normal state when dropped, so it is
+/// restored even when drawing fails or the interface panics.
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture);
+    }
+}
+
+pub fn main_loop(app: Arc<Mutex<Application>>, theme: Theme, keys: KeyBindings) -> Result<(), Box<dyn Error>> {
 
     enable_raw_mode()?;
+    let _terminal_guard = TerminalGuard;
 
     let mut stdout = std::io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
@@ -49,19 +64,23 @@ pub fn main_loop(app: Arc<Mutex<Application>>) -> Result<(), Box<dyn Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let events = Events::new();
+    let events = Events::with_config(event::Config {
+        exit_key: keys.quit,
+        ..event::Config::default()
+    });
+    let palette = Palette::new(theme);
 
     loop {
         let active_thread_count = {
             let app = app.lock().unwrap();
-            (*app).threads.len()
+            app.threads.len()
         };
 
         terminal.draw(|f| {
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints([
-                    Constraint::Length(6),
+                    Constraint::Length(11),
                     Constraint::Length(active_thread_count as u16 + 2),
                     Constraint::Percentage(50),
                 ].as_ref())
@@ -69,53 +88,41 @@ pub fn main_loop(app: Arc<Mutex<Application>>) -> Result<(), Box<dyn Error>> {
             let app = App::from(&app);
             let stats = extract_statistics(App::clone(&app));
             let thread_statuses = extract_thread_statuses(App::clone(&app));
-            let messages = extract_log_messages(App::clone(&app));
+            let messages = extract_log_messages(App::clone(&app), &palette);
 
-            draw_app_stats_window(f, chunks[0], stats);
-            draw_gauge_window(f, chunks[1], thread_statuses);
+            draw_app_stats_window(f, chunks[0], stats, &keys, &palette);
+            draw_gauge_window(f, chunks[1], thread_statuses, &palette);
             draw_log_window(f, chunks[2], messages);
         })?;
 
 
 
-        match events.next()? {
-            Event::Input(input) => match input {
-                KeyCode::Char('q') => {
-                    let mut app = app.lock().unwrap();
-                    (*app).quitting = true;
+        if let Event::Input(input) = events.next()? {
+            if input == keys.quit {
+                let mut app = app.lock().unwrap();
+                app.quitting = true;
+            } else if input == keys.more_threads {
+                let mut app = app.lock().unwrap();
+                if app.expected_thread_count < MAX_THREADS {
+                    app.expected_thread_count += 1;
                 }
-                KeyCode::Up => {
-                    let mut app = app.lock().unwrap();
-                    (*app).expected_thread_count += 1;
+            } else if input == keys.fewer_threads {
+                let mut app = app.lock().unwrap();
+                if app.expected_thread_count != 0 {
+                    app.expected_thread_count -= 1;
                 }
-                KeyCode::Down => {
-                    let mut app = app.lock().unwrap();
-                    if (*app).expected_thread_count != 0 {
-                        (*app).expected_thread_count -= 1;
-                    }
-                }
-                _ => {}
-            },
-            _ => (),
+            }
         }
 
         // Check if the ui can end.
         {
             let app = app.lock().unwrap();
-            if (*app).quitting && (*app).threads_cleaned_up {
+            if app.quitting && app.threads_cleaned_up {
                 break;
             }
         }
     }
 
-
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-
     Ok(())
 }
 
@@ -129,13 +136,34 @@ struct Statistics {
     student_number: String,
     name: String,
     thread_count: u8,
+    hash_backend: BackendKind,
+    difficulty: Option<u8>,
+    accepted_submissions: usize,
+    rejected_submissions: usize,
+    pending_submissions: usize,
+    buffered_jobs: usize,
+    buffer_depth: usize,
+    waiting_for_jobs: f64,
+    server_url: String,
+    network_error: Option<String>,
+    updates: &'static str,
+    /// The proxy and where it was configured.
+    proxy: Option<(String, String)>,
+    /// Average round trip of each endpoint.
+    latencies: Vec<(String, Duration)>,
     quitting: bool,
 }
 
-fn draw_app_stats_window<B: Backend>(f: &mut Frame<B>, area: Rect, stats: Statistics) {
+fn draw_app_stats_window<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    stats: Statistics,
+    keys: &KeyBindings,
+    palette: &Palette,
+) {
     if stats.quitting {
         let info_line_items = vec![ListItem::new(vec![
-            Spans::from(format!("  Shutting down... Please wait."))
+            Spans::from("  Shutting down... Please wait.")
         ])];
         let items = List::new(info_line_items)
             .block(Block::default().borders(Borders::ALL).title(" Hasher 0.2 - Info "));
@@ -144,7 +172,12 @@ fn draw_app_stats_window<B: Backend>(f: &mut Frame<B>, area: Rect, stats: Statis
     }
     let info_line_items = vec![
         ListItem::new(vec![
-            Spans::from(format!("  (Q) - Quit, (up) - Inc threads, (down) - Dec threads"))
+            Spans::from(format!(
+                "  ({}) - Quit, ({}) - Inc threads, ({}) - Dec threads",
+                key_name(keys.quit),
+                key_name(keys.more_threads),
+                key_name(keys.fewer_threads),
+            ))
         ]),
         ListItem::new(vec![
             Spans::from(
@@ -160,8 +193,9 @@ fn draw_app_stats_window<B: Backend>(f: &mut Frame<B>, area: Rect, stats: Statis
         ListItem::new(vec![
             Spans::from(
                 format!(
-                    "  Pool Best Zero Length: {}/48, Your total hashrate: {:.02} MH/s",
+                    "  Pool Best Zero Length: {}/48, Target: {}, Your total hashrate: {:.02} MH/s",
                     stats.best_bit_length,
+                    stats.difficulty.map_or_else(|| String::from("-"), |bits| bits.to_string()),
                     stats.user_hash_rate,
                 )
             )
@@ -169,13 +203,63 @@ fn draw_app_stats_window<B: Backend>(f: &mut Frame<B>, area: Rect, stats: Statis
         ListItem::new(vec![
             Spans::from(
                 format!(
-                    "  Threads: {}, Student number: {}, client: {}",
+                    "  Threads: {} ({}), Student number: {}, client: {}",
                     stats.thread_count,
+                    stats.hash_backend,
                     stats.student_number,
                     stats.name,
                 )
             )
         ]),
+        ListItem::new(vec![
+            Spans::from(
+                format!(
+                    "  Submissions accepted: {}, rejected: {}, pending: {}",
+                    stats.accepted_submissions,
+                    stats.rejected_submissions,
+                    stats.pending_submissions,
+                )
+            )
+        ]),
+        ListItem::new(vec![
+            Spans::from(
+                format!(
+                    "  Job buffer: {}/{}, Time waiting for jobs: {:.1}s",
+                    stats.buffered_jobs,
+                    stats.buffer_depth,
+                    stats.waiting_for_jobs,
+                )
+            )
+        ]),
+        ListItem::new(vec![
+            Spans::from(vec![
+                Span::raw(format!("  Pool: {}, Connection: ", stats.server_url)),
+                match stats.network_error {
+                    Some(error) => Span::styled(error, palette.error),
+                    None => Span::styled("OK", palette.ok),
+                },
+                Span::raw(format!(", Updates: {}", stats.updates)),
+            ])
+        ]),
+        ListItem::new(vec![
+            Spans::from(match &stats.proxy {
+                Some((proxy, source)) => format!("  Proxy: {} (from {})", proxy, source),
+                None => String::from("  Proxy: none, connecting directly"),
+            })
+        ]),
+        ListItem::new(vec![
+            Spans::from(format!(
+                "  Latency: {}",
+                if stats.latencies.is_empty() {
+                    String::from("-")
+                } else {
+                    stats.latencies.iter()
+                        .map(|(endpoint, average)| format!("{} {}ms", endpoint, average.as_millis()))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                },
+            ))
+        ]),
     ];
     let items = List::new(info_line_items)
         .block(Block::default().borders(Borders::ALL).title(" Hasher 0.2 - Info "));
@@ -189,7 +273,7 @@ struct ThreadStatus {
 }
 
 
-fn draw_gauge_window<B: Backend>(f: &mut Frame<B>, area: Rect, thread_statuses: Vec<ThreadStatus>) {
+fn draw_gauge_window<B: Backend>(f: &mut Frame<B>, area: Rect, thread_statuses: Vec<ThreadStatus>, palette: &Palette) {
 
     let constraints = thread_statuses.iter().map(|_| Constraint::Length(1)).collect::<Vec<Constraint>>();
 
@@ -223,8 +307,7 @@ fn draw_gauge_window<B: Backend>(f: &mut Frame<B>, area: Rect, thread_statuses:
         let gauge = Gauge::default()
             // .block(Block::default().title("Gauge:"))
             .gauge_style(
-                get_gauge_style(i)
-                .bg(Color::Black)
+                palette.gauge(i)
                 .add_modifier(Modifier::ITALIC | Modifier::BOLD),
             )
             .label(label)
@@ -240,16 +323,58 @@ fn draw_log_window<B: Backend>(f: &mut Frame<B>, area: Rect, items: Vec<ListItem
     f.render_widget(items, area);
 }
 
-fn get_gauge_style(i: usize) -> Style {
-    let style_colours: Vec<Style> = vec![
-        Style::default().fg(Color::Magenta),
-        Style::default().fg(Color::Cyan),
-        Style::default().fg(Color::Blue),
-        Style::default().fg(Color::Green),
-        Style::default().fg(Color::Yellow),
-        Style::default().fg(Color::Red),
-    ];
-    style_colours[i%style_colours.len()].clone()
+/// The colours of a theme.
+struct Palette {
+    ok: Style,
+    error: Style,
+    info: Style,
+    zeros: Style,
+    gauges: Vec<Color>,
+    gauge_background: Color,
+}
+
+impl Palette {
+    fn new(theme: Theme) -> Self {
+        match theme {
+            Theme::Default => Palette {
+                ok: Style::default().fg(Color::Green),
+                error: Style::default().fg(Color::Red),
+                info: Style::default().fg(Color::LightBlue),
+                zeros: Style::default().fg(Color::Cyan),
+                gauges: vec![
+                    Color::Magenta,
+                    Color::Cyan,
+                    Color::Blue,
+                    Color::Green,
+                    Color::Yellow,
+                    Color::Red,
+                ],
+                gauge_background: Color::Black,
+            },
+            Theme::Light => Palette {
+                ok: Style::default().fg(Color::Green),
+                error: Style::default().fg(Color::Red),
+                info: Style::default().fg(Color::Blue),
+                zeros: Style::default().fg(Color::Magenta),
+                gauges: vec![Color::Magenta, Color::Blue, Color::Green, Color::Red],
+                gauge_background: Color::Gray,
+            },
+            Theme::Mono => Palette {
+                ok: Style::default(),
+                error: Style::default().add_modifier(Modifier::BOLD),
+                info: Style::default(),
+                zeros: Style::default().add_modifier(Modifier::BOLD),
+                gauges: vec![Color::White],
+                gauge_background: Color::Black,
+            },
+        }
+    }
+
+    fn gauge(&self, i: usize) -> Style {
+        Style::default()
+            .fg(self.gauges[i % self.gauges.len()])
+            .bg(self.gauge_background)
+    }
 }
 
 
@@ -293,13 +418,28 @@ fn extract_statistics(mut app: App) -> Statistics {
             student_number: String::from(&app.student_number),
             name: String::from(&app.name),
             thread_count: app.expected_thread_count as u8,
+            hash_backend: app.hash_backend,
+            difficulty: app.difficulty,
+            accepted_submissions: app.accepted_submissions,
+            rejected_submissions: app.rejected_submissions,
+            pending_submissions: app.submission_queue.len(),
+            buffered_jobs: app.job_buffer.len(),
+            buffer_depth: JobBuffer::depth(app.expected_thread_count),
+            waiting_for_jobs: app.job_buffer.waiting.as_secs_f64(),
+            server_url: app.server_url.clone(),
+            network_error: app.network_error.as_ref().map(|error| error.to_string()),
+            updates: if app.push_connected { "push" } else { "polling" },
+            proxy: app.proxy.as_ref().map(|proxy| (proxy.to_string(), proxy.source.clone())),
+            latencies: app.latencies.iter()
+                .map(|(endpoint, latency)| (String::from(endpoint), latency.average))
+                .collect(),
             quitting: app.quitting,
         }
     })
 }
 
 
-fn extract_log_messages<'a>(mut app: App) -> Vec<ListItem<'a>> {
+fn extract_log_messages<'a>(mut app: App, palette: &Palette) -> Vec<ListItem<'a>> {
     let logs = app.lock(|app| {
         let messages = app.log.get();
         messages.clone()
@@ -312,7 +452,7 @@ fn extract_log_messages<'a>(mut app: App) -> Vec<ListItem<'a>> {
                     // Catergories message
                     let mut line = vec![
                         Span::raw(" [ "),
-                        Span::styled("OK", Style::default().fg(Color::Green)),
+                        Span::styled("OK", palette.ok),
                         Span::raw(" ]   "),
                     ];
 
@@ -323,7 +463,7 @@ fn extract_log_messages<'a>(mut app: App) -> Vec<ListItem<'a>> {
 
                     // Add sha256
                     let zeros = String::from(&hash[..zn]);
-                    let zeros = Span::styled(zeros, Style::default().fg(Color::Cyan));
+                    let zeros = Span::styled(zeros, palette.zeros);
                     let rest = String::from(&hash[zn..]);
                     let rest  = Span::raw(rest);
                     line.push(zeros);
@@ -341,7 +481,7 @@ fn extract_log_messages<'a>(mut app: App) -> Vec<ListItem<'a>> {
                     // Catergories message
                     let mut line = vec![
                         Span::raw(" [ "),
-                        Span::styled("ERR", Style::default().fg(Color::Red)),
+                        Span::styled("ERR", palette.error),
                         Span::raw(" ]  "),
                     ];
                     
@@ -355,7 +495,7 @@ fn extract_log_messages<'a>(mut app: App) -> Vec<ListItem<'a>> {
                     // Catergories message
                     let mut line = vec![
                         Span::raw(" [ "),
-                        Span::styled("INF", Style::default().fg(Color::LightBlue)),
+                        Span::styled("INF", palette.info),
                         Span::raw(" ]  "),
                     ];
                     
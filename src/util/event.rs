@@ -1,4 +1,3 @@
-use std::io;
 use std::sync::mpsc;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
@@ -10,11 +9,7 @@ use std::time::Duration;
 // use termion::event::Key;
 use crossterm::event::KeyCode as Key;
 // use termion::input::TermRead;
-use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event as CEvent, KeyCode},
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-};
+use crossterm::event::{self, Event as CEvent, KeyCode, KeyModifiers};
 use std::{
     time::{Instant},
 };
@@ -66,7 +61,12 @@ impl Events {
                         .checked_sub(last_tick.elapsed())
                         .unwrap_or_else(|| Duration::from_secs(0));
                     if event::poll(timeout).unwrap() {
-                        if let CEvent::Key(key) = event::read().unwrap() {
+                        if let CEvent::Key(mut key) = event::read().unwrap() {
+                            // Raw mode turns Ctrl-C into a key press instead
+                            // of SIGINT, treat it like the exit key.
+                            if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                                key.code = config.exit_key;
+                            }
                             if let Err(err) = tx.send(Event::Input(key.code)) {
                                 eprintln!("{}", err);
                                 return;
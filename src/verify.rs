@@ -0,0 +1,109 @@
+use std::error::Error;
+use std::fmt;
+
+use radix_fmt::radix;
+use sha2::{Digest, Sha256};
+
+use crate::miner::{count_leading_zero_bits, sha245_to_string};
+use crate::net::packets::Solution;
+
+/// The reasons a submitted solution can be refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The nonce is not made of upper case base-36 digits as produced by the
+    /// miner.
+    InvalidNounce(String),
+    /// The recomputed hash does not match the submitted one.
+    HashMismatch { expected: String, submitted: String },
+    /// The hash does not have enough leading zero bits.
+    InsufficientZeroBits { found: u8, required: u8 },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::InvalidNounce(nounce) => {
+                write!(f, "invalid nonce \"{}\"", nounce)
+            }
+            VerifyError::HashMismatch { expected, submitted } => {
+                write!(f, "hash mismatch, expected {} but {} was submitted", expected, submitted)
+            }
+            VerifyError::InsufficientZeroBits { found, required } => {
+                write!(f, "{} leading zero bits, at least {} required", found, required)
+            }
+        }
+    }
+}
+
+impl Error for VerifyError {}
+
+/// Check the nonce only contains the upper case base-36 digits the miner
+/// produces.
+pub fn is_valid_nounce(nounce: &str) -> bool {
+    !nounce.is_empty() && nounce.bytes().all(|b| b.is_ascii_digit() || b.is_ascii_uppercase())
+}
+
+/// The position of a nonce within a job starting at `nounce_start`, in the
+/// order the miner hashes them. The miner starts from the base-36 form of
+/// `nounce_start` and increments the first digit, carrying into the
+/// following ones, so the digits are read least significant first.
+pub fn nounce_offset(nounce_start: u64, nounce: &str) -> Option<u64> {
+    let start = radix(nounce_start, 36).to_string().to_uppercase();
+    if !is_valid_nounce(nounce) {
+        return None;
+    }
+    let offset = reversed_value(nounce)?.checked_sub(reversed_value(&start)?)?;
+
+    // The miner never shortens the nonce, so the same value can only appear
+    // padded to the length of the starting nonce.
+    if nounce_at(nounce_start, offset)? != nounce {
+        return None;
+    }
+    Some(offset)
+}
+
+/// The nonce the miner hashes `offset` nonces after the start of a job
+/// starting at `nounce_start`, the inverse of `nounce_offset`.
+pub fn nounce_at(nounce_start: u64, offset: u64) -> Option<String> {
+    let start = radix(nounce_start, 36).to_string().to_uppercase();
+    let value = reversed_value(&start)?.checked_add(offset)?;
+    let mut nounce: String = radix(value, 36).to_string().to_uppercase().chars().rev().collect();
+    while nounce.len() < start.len() {
+        nounce.push('0');
+    }
+    Some(nounce)
+}
+
+/// The value of a nonce read least significant digit first.
+fn reversed_value(nounce: &str) -> Option<u64> {
+    let reversed: String = nounce.chars().rev().collect();
+    u64::from_str_radix(&reversed, 36).ok()
+}
+
+/// Recompute the hash of the student number followed by the solution's
+/// nonce and check it against the submitted hash. Returns the number of
+/// leading zero bits of a valid solution.
+pub fn verify_solution(student_number: &str, solution: &Solution, min_zero_bits: u8) -> Result<u8, VerifyError> {
+    if !is_valid_nounce(&solution.nounce) {
+        return Err(VerifyError::InvalidNounce(solution.nounce.clone()));
+    }
+
+    let mut sh = Sha256::default();
+    sh.update(student_number.as_bytes());
+    sh.update(solution.nounce.as_bytes());
+    let sha256_buffer = sh.finalize();
+
+    let expected = sha245_to_string(&sha256_buffer);
+    if expected != solution.sha256.to_lowercase() {
+        return Err(VerifyError::HashMismatch {
+            expected,
+            submitted: solution.sha256.clone(),
+        });
+    }
+
+    let found = count_leading_zero_bits(&sha256_buffer);
+    if found < min_zero_bits {
+        return Err(VerifyError::InsufficientZeroBits { found, required: min_zero_bits });
+    }
+    Ok(found)
+}
@@ -0,0 +1,52 @@
+//! The `hasher` program as started from a shell: a broken environment must
+//! end in the documented exit code, never in a panic.
+
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Exit codes of `CliError::Config` and `CliError::Network`.
+const CONFIG: i32 = 12;
+const NETWORK: i32 = 13;
+
+#[test]
+fn invalid_proxy_variable_is_a_configuration_error() {
+    let output = mine("bad-proxy", &[], "ftp://proxy.invalid:21");
+    assert_eq!(output.status.code(), Some(CONFIG), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("HTTP_PROXY"));
+}
+
+#[test]
+fn proxy_setting_of_none_ignores_the_variable() {
+    let output = mine("no-proxy", &["--proxy", "none"], "ftp://proxy.invalid:21");
+    assert_eq!(output.status.code(), Some(NETWORK), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+/// Mine against a port nobody listens on, with `HTTP_PROXY` set and the
+/// configuration and journal in a directory of the test's own, also the
+/// working directory.
+fn mine(test: &str, args: &[&str], http_proxy: &str) -> Output {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let home = std::env::temp_dir().join(format!("hasher-cli-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&home);
+    std::fs::create_dir_all(&home).unwrap();
+    let output = Command::new(PathBuf::from(env!("CARGO_BIN_EXE_hasher")))
+        .args(["mine", "--student-number", "12345678", "--name", "cli-check"])
+        .arg("--server")
+        .arg(format!("http://127.0.0.1:{}", port))
+        .args(args)
+        .current_dir(&home)
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_STATE_HOME", home.join("state"))
+        .env("HTTP_PROXY", http_proxy)
+        .env_remove("http_proxy")
+        .env_remove("ALL_PROXY")
+        .env_remove("all_proxy")
+        .env_remove("NO_PROXY")
+        .env_remove("no_proxy")
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&home);
+    output
+}
@@ -0,0 +1,193 @@
+//! The partial submission protocol against the bundled pool. A real mining
+//! thread is stopped part way through a job, then the rest of the job is
+//! requested again and hashed. Together the two submissions must cover the
+//! job exactly: every solution counted once and none lost. The job the
+//! dispatcher had buffered must be handed back to the pool untouched.
+//!
+//! The client talks to the pool through the in-memory transport, so no
+//! network is involved.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use hasher::application::{App, Application, ThreadState};
+use hasher::dispatcher;
+use hasher::hashing::BackendKind;
+use hasher::miner::{self, hash_job, HashedRange};
+use hasher::net::packets::{
+    Job,
+    JobRequestPacket,
+    JobResponsePacket,
+    PoolStatusRequestPacket,
+    Solution,
+    SubmittionPacket,
+    SubmittionResponsePacket,
+};
+use hasher::net::ConnectSettings;
+use hasher::net::queue::SubmissionQueue;
+use hasher::net::transport::MemoryTransport;
+use hasher::pool::{Pool, PoolConfig};
+use hasher::verify::nounce_offset;
+
+const STUDENT_NUMBER: &str = "12345678";
+const NAME: &str = "partial-check";
+const JOB_SIZE: u64 = 1_000_000;
+const DIFFICULTY: u8 = 10;
+
+#[test]
+fn nounce_end_follows_the_last_hashed_nonce() {
+    // At difficulty 0 every hash is a solution, so the solutions are the
+    // nonces hashed, in order.
+    let job = Job {
+        number: 1,
+        size: JOB_SIZE,
+        nounce_start: 1234,
+        nounce_end: 1234 + JOB_SIZE,
+        difficulty: 0,
+        offset: 77,
+        ttl: None,
+    };
+    for kind in BackendKind::ALL.iter().copied().filter(BackendKind::is_supported) {
+        let backend = kind.build(STUDENT_NUMBER.as_bytes()).unwrap();
+        let mut reports = 0;
+        let HashedRange { nounce_end, solutions } = hash_job(
+            backend.as_ref(),
+            &job,
+            |_, _| {
+                reports += 1;
+                reports < 3
+            },
+            |_, _| {},
+        );
+        assert!(nounce_end < job.nounce_end, "{} did not stop", kind);
+        let offsets: Vec<u64> = solutions
+            .iter()
+            .map(|solution| nounce_offset(job.nounce_start, &solution.nounce).unwrap())
+            .collect();
+        let expected: Vec<u64> = (job.offset..nounce_end - job.nounce_start).collect();
+        assert_eq!(offsets, expected, "{} hashed other nonces than it reported", kind);
+        assert_eq!(job.nounce_start + offsets.last().unwrap() + 1, nounce_end, "{}", kind);
+    }
+}
+
+#[test]
+fn stopped_job_is_reissued_without_losing_solutions() {
+    let pool = Arc::new(Pool::new(PoolConfig {
+        bind: String::new(),
+        stratum_bind: None,
+        job_size: JOB_SIZE,
+        min_zero_bits: DIFFICULTY,
+        workers: 2,
+        job_ttl: None,
+        secrets: None,
+    }));
+
+    // Mine on one thread until part of the first job is done, then stop the
+    // thread and quit the way the Q key does.
+    let queue_path = std::env::temp_dir().join(format!("hasher-partial-check-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&queue_path);
+    let mut application = Application::start(
+        String::from(STUDENT_NUMBER),
+        1,
+        String::from(NAME),
+        // Replaced by the in-memory transport below.
+        String::from("http://memory"),
+        &ConnectSettings::default(),
+        SubmissionQueue::open(&queue_path).unwrap(),
+        BackendKind::Scalar,
+    ).unwrap();
+    application.transport = Arc::new(MemoryTransport::new(Arc::clone(&pool)));
+    let app = Arc::new(Mutex::new(application));
+    let dispatcher_thread = dispatcher::begin(App::from(&app));
+    let miner_thread = miner::begin(Arc::clone(&app));
+    loop {
+        thread::sleep(Duration::from_millis(1));
+        let app = app.lock().unwrap();
+        let progress = app.threads.first()
+            .and_then(|thread| thread.current_job.lock().unwrap().as_ref().map(|job| job.progress))
+            .unwrap_or(0);
+        if progress > 0 {
+            *app.threads[0].state.lock().unwrap() = ThreadState::StopSignal;
+            break;
+        }
+    }
+    app.lock().unwrap().quitting = true;
+    miner_thread.join().unwrap();
+    dispatcher_thread.join().unwrap();
+    {
+        let app = app.lock().unwrap();
+        assert_eq!(app.accepted_submissions, 2, "the partial and released jobs were not accepted");
+        assert!(app.submission_queue.is_empty(), "the partial submission was queued");
+    }
+    let partial_shares = shares(&pool);
+
+    // The pool hands out the rest of the first job and the whole second job
+    // next, in either order.
+    let mut reissued = vec![request_job(&pool), request_job(&pool)];
+    reissued.sort_by_key(|job| job.nounce_start);
+    let released = reissued.pop().unwrap();
+    assert_eq!(released.nounce_start, JOB_SIZE, "the buffered job was not handed back");
+    assert_eq!(released.nounce_end, 2 * JOB_SIZE);
+    assert_eq!(released.offset, 0);
+    let rest = reissued.pop().unwrap();
+    assert_eq!(rest.nounce_start, 0, "the rest of the first job was not reissued");
+    assert_eq!(rest.nounce_end, JOB_SIZE);
+    assert!(rest.offset > 0 && rest.offset < JOB_SIZE, "unexpected offset {}", rest.offset);
+    let backend = BackendKind::Scalar.build(STUDENT_NUMBER.as_bytes()).unwrap();
+    let whole = Job { offset: 0, ..rest };
+    let HashedRange { solutions: expected, .. } = hash_job(backend.as_ref(), &whole, |_, _| true, |_, _| {});
+    let before_offset = expected
+        .iter()
+        .filter(|solution| nounce_offset(whole.nounce_start, &solution.nounce).unwrap() < rest.offset)
+        .count();
+    assert_eq!(before_offset, partial_shares, "the reissued offset does not follow the partial submission");
+
+    // A partial flag that does not match the range is refused.
+    let mut packet = submission(&rest, JOB_SIZE, vec![]);
+    packet.partial = true;
+    assert!(matches!(pool.submit_job(packet), SubmittionResponsePacket::RejectedWithReason(_)));
+
+    // Hash and submit the rest.
+    let HashedRange { nounce_end, solutions } = hash_job(backend.as_ref(), &rest, |_, _| true, |_, _| {});
+    assert_eq!(nounce_end, JOB_SIZE);
+    assert_eq!(solutions.len(), expected.len() - partial_shares);
+    let response = pool.submit_job(submission(&rest, nounce_end, solutions));
+    assert!(matches!(response, SubmittionResponsePacket::Accepted), "{:?}", response);
+
+    // Both parts together found the solutions of the whole job.
+    assert_eq!(shares(&pool), expected.len());
+    let _ = std::fs::remove_file(&queue_path);
+}
+
+fn submission(job: &Job, nounce_end: u64, solutions: Vec<Solution>) -> SubmittionPacket {
+    SubmittionPacket {
+        job_n: job.number,
+        name: String::from(NAME),
+        student_number: String::from(STUDENT_NUMBER),
+        thread_hashes_per_second: 0.0,
+        total_hashes_per_second: 0.0,
+        nounce_start: job.nounce_start,
+        nounce_end,
+        solutions,
+        offset: job.offset,
+        partial: nounce_end < job.nounce_end,
+        explain_rejections: true,
+    }
+}
+
+fn request_job(pool: &Pool) -> Job {
+    match pool.request_job(JobRequestPacket {
+        student_number: String::from(STUDENT_NUMBER),
+        name: String::from(NAME),
+    }) {
+        JobResponsePacket::Success(job) => job,
+        JobResponsePacket::Error(message) => panic!("No job: {}", message),
+    }
+}
+
+fn shares(pool: &Pool) -> usize {
+    pool.status(PoolStatusRequestPacket {
+        student_number: String::from(STUDENT_NUMBER),
+    }).user_total_shares
+}
@@ -0,0 +1,213 @@
+//! The push connection against the bundled pool served over HTTP. Every
+//! kind of push message must reach the job buffer or the log, and once the
+//! connection drops the dispatcher must poll for jobs again.
+//!
+//! The client reaches the pool through a relay in this test, so the push
+//! connection can be cut while the pool keeps answering requests.
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hasher::application::{App, Application, ThreadState};
+use hasher::dispatcher::{self, JobBuffer};
+use hasher::hashing::BackendKind;
+use hasher::log::LogMessage;
+use hasher::net::{proxy, push, ConnectSettings};
+use hasher::net::packets::{Job, SubmittionPacket, SubmittionResponsePacket};
+use hasher::net::queue::SubmissionQueue;
+use hasher::net::retry::RetryPolicy;
+use hasher::pool::{self, Pool, PoolConfig};
+
+const STUDENT_NUMBER: &str = "12345678";
+const NAME: &str = "push-check";
+const THREADS: usize = 3;
+const PATIENCE: Duration = Duration::from_secs(20);
+
+#[test]
+fn push_messages_reach_the_miner_and_polling_resumes_without_them() {
+    let pool = serve(None);
+    let relay = Relay::start(pool.config().bind.clone());
+    let (app, push_thread) = subscribe(&relay, "messages");
+
+    pool.set_difficulty(12);
+    wait_for(&app, "the difficulty message", |app| logged(app, "The pool now issues jobs at difficulty 12."));
+    pool.broadcast("back in five minutes");
+    wait_for(&app, "the broadcast", |app| logged(app, "Message from the pool: back in five minutes"));
+
+    // Connected, the dispatcher keeps a single job buffered and leaves the
+    // rest to the pool.
+    let dispatcher_thread = dispatcher::begin(App::from(&app));
+    wait_for(&app, "a buffered job", |app| app.job_buffer.len() == 1);
+    let job = dispatcher::take_job(App::from(&app), &Arc::new(Mutex::new(ThreadState::Mining)))
+        .expect("The buffered job is taken.");
+    wait_for(&app, "the buffer to be filled again", |app| app.job_buffer.len() == 1);
+    let response = pool.submit_job(submission(&job));
+    assert!(matches!(response, SubmittionResponsePacket::Accepted), "{:?}", response);
+    wait_for(&app, "the pushed job", |app| app.job_buffer.len() == 2);
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(app.lock().unwrap().job_buffer.len(), 2, "the dispatcher fetched while connected");
+
+    // Without the connection the dispatcher fills the buffer itself.
+    relay.cut_push_connections();
+    wait_for(&app, "the lost connection", |app| !app.push_connected && logged(app, "Lost the push connection"));
+    wait_for(&app, "polled jobs", |app| app.job_buffer.len() == JobBuffer::depth(THREADS));
+
+    app.lock().unwrap().quitting = true;
+    push_thread.join().unwrap();
+    dispatcher_thread.join().unwrap();
+}
+
+#[test]
+fn stale_jobs_are_reported_over_the_push_connection() {
+    let pool = serve(Some(1));
+    let relay = Relay::start(pool.config().bind.clone());
+    let (app, push_thread) = subscribe(&relay, "stale");
+
+    // The dispatcher drops a job halfway through its time, before the pool
+    // does, so the job is gone from the buffer by the time it goes stale.
+    let dispatcher_thread = dispatcher::begin(App::from(&app));
+    wait_for(&app, "a stale job", |app| logged(app, "went stale at the pool"));
+
+    app.lock().unwrap().quitting = true;
+    push_thread.join().unwrap();
+    dispatcher_thread.join().unwrap();
+}
+
+/// Serve a pool on a free port. The server runs until the test ends.
+fn serve(job_ttl: Option<u64>) -> Arc<Pool> {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let pool = Arc::new(Pool::new(PoolConfig {
+        bind: format!("127.0.0.1:{}", port),
+        stratum_bind: None,
+        job_size: 1_000,
+        min_zero_bits: 40,
+        workers: 2,
+        job_ttl,
+        secrets: None,
+    }));
+    let server = Arc::clone(&pool);
+    thread::spawn(move || pool::http::serve(server).unwrap());
+    let started = Instant::now();
+    while TcpStream::connect(&pool.config().bind).is_err() {
+        assert!(started.elapsed() < PATIENCE, "the pool did not start");
+        thread::sleep(Duration::from_millis(10));
+    }
+    pool
+}
+
+/// Start an application talking to the pool through the relay, and wait
+/// until its push connection is subscribed. Reconnecting takes a minute,
+/// longer than any test waits.
+fn subscribe(relay: &Relay, test: &str) -> (Arc<Mutex<Application>>, thread::JoinHandle<()>) {
+    let queue_path = std::env::temp_dir().join(format!("hasher-push-{}-{}.jsonl", test, std::process::id()));
+    let _ = std::fs::remove_file(&queue_path);
+    let server_url = format!("http://{}", relay.address);
+    let mut application = Application::start(
+        String::from(STUDENT_NUMBER),
+        THREADS,
+        String::from(NAME),
+        server_url,
+        &ConnectSettings { proxy: Some(String::from(proxy::DIRECT)), ..ConnectSettings::default() },
+        SubmissionQueue::open(&queue_path).unwrap(),
+        BackendKind::Scalar,
+    ).unwrap();
+    application.retry_policy = RetryPolicy {
+        base_delay: Duration::from_secs(60),
+        max_delay: Duration::from_secs(60),
+        ..RetryPolicy::default()
+    };
+    application.push = true;
+    let app = Arc::new(Mutex::new(application));
+    let push_thread = push::begin(App::from(&app));
+    // The pool sends the statistics first thing after the subscription.
+    wait_for(&app, "the subscription", |app| app.push_connected && app.pool_status.is_some());
+    (app, push_thread)
+}
+
+fn wait_for(app: &Arc<Mutex<Application>>, what: &str, condition: impl Fn(&Application) -> bool) {
+    let started = Instant::now();
+    while !condition(&app.lock().unwrap()) {
+        assert!(started.elapsed() < PATIENCE, "gave up waiting for {}", what);
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn logged(app: &Application, text: &str) -> bool {
+    app.log.get().iter().any(|message| match message {
+        LogMessage::Info(message) | LogMessage::Error(message) => message.contains(text),
+        LogMessage::Solution { .. } => false,
+    })
+}
+
+fn submission(job: &Job) -> SubmittionPacket {
+    SubmittionPacket {
+        job_n: job.number,
+        name: String::from(NAME),
+        student_number: String::from(STUDENT_NUMBER),
+        thread_hashes_per_second: 0.0,
+        total_hashes_per_second: 0.0,
+        nounce_start: job.nounce_start,
+        nounce_end: job.nounce_end,
+        solutions: vec![],
+        offset: job.offset,
+        partial: false,
+        explain_rejections: true,
+    }
+}
+
+/// Forwards connections to the pool, keeping hold of the push connections
+/// so they can be cut.
+struct Relay {
+    address: String,
+    push_connections: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl Relay {
+    fn start(target: String) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let relay = Relay {
+            address: listener.local_addr().unwrap().to_string(),
+            push_connections: Arc::default(),
+        };
+        let push_connections = Arc::clone(&relay.push_connections);
+        thread::spawn(move || {
+            for client in listener.incoming() {
+                let (client, upstream) = match client.and_then(|client| Ok((client, TcpStream::connect(&target)?))) {
+                    Ok(pair) => pair,
+                    Err(_) => continue,
+                };
+                let push_connections = Arc::clone(&push_connections);
+                thread::spawn(move || forward(client, upstream, &push_connections));
+            }
+        });
+        relay
+    }
+
+    /// Shut down every push connection relayed so far.
+    fn cut_push_connections(&self) {
+        for connection in self.push_connections.lock().unwrap().drain(..) {
+            let _ = connection.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+fn forward(mut client: TcpStream, mut upstream: TcpStream, push_connections: &Mutex<Vec<TcpStream>>) -> io::Result<()> {
+    let mut first = [0; 1024];
+    let length = client.read(&mut first)?;
+    if first[..length].starts_with(b"GET /push") {
+        push_connections.lock().unwrap().push(client.try_clone()?);
+    }
+    upstream.write_all(&first[..length])?;
+    let (mut client_reader, mut upstream_writer) = (client.try_clone()?, upstream.try_clone()?);
+    let upload = thread::spawn(move || {
+        let _ = io::copy(&mut client_reader, &mut upstream_writer);
+        let _ = upstream_writer.shutdown(Shutdown::Both);
+    });
+    let _ = io::copy(&mut upstream, &mut client);
+    let _ = client.shutdown(Shutdown::Both);
+    let _ = upload.join();
+    Ok(())
+}
@@ -0,0 +1,221 @@
+//! https:// pools and their push connection, with the `tls` feature. The
+//! bundled pool speaks plain HTTP, so it is put behind a TLS stand-in that
+//! presents `tests/tls/pool.pem` and only lets in miners with a certificate
+//! signed by `tests/tls/ca.pem`.
+//!
+//! The certificates are valid for a hundred years. To make new ones, create
+//! a CA with `openssl req -x509` using a P-256 key and `CA:TRUE`, and sign a
+//! certificate for `DNS:localhost` with `serverAuth` for the pool and one
+//! with `clientAuth` for the miner. The keys must be PKCS#8.
+
+#![cfg(feature = "tls")]
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rustls::internal::pemfile::{certs, pkcs8_private_keys};
+use rustls::{AllowAnyAuthenticatedClient, RootCertStore, ServerConfig, ServerSession, Session};
+
+use hasher::application::{App, Application};
+use hasher::hashing::BackendKind;
+use hasher::net::{self, proxy, push, ConnectSettings, TlsSettings};
+use hasher::net::error::NetError;
+use hasher::net::packets::{BootRequest, CommandResponse};
+use hasher::net::queue::SubmissionQueue;
+use hasher::pool::{self, Pool, PoolConfig};
+
+#[test]
+fn pool_signed_by_an_unknown_ca_is_refused() {
+    let server_url = serve();
+    let tls = TlsSettings {
+        ca_file: None,
+        client_cert: Some(certificate("client.pem")),
+        client_key: Some(certificate("client.key")),
+    };
+    match boot(&server_url, tls) {
+        Err(NetError::Transport { message, .. }) => assert!(message.contains("UnknownIssuer"), "{}", message),
+        other => panic!("The pool was trusted: {:?}", other),
+    }
+}
+
+#[test]
+fn miner_without_a_client_certificate_is_refused() {
+    let server_url = serve();
+    let tls = TlsSettings {
+        ca_file: Some(certificate("ca.pem")),
+        client_cert: None,
+        client_key: None,
+    };
+    match boot(&server_url, tls) {
+        Err(NetError::Transport { message, .. }) => assert!(message.contains("CertificateRequired"), "{}", message),
+        other => panic!("The miner was let in: {:?}", other),
+    }
+}
+
+#[test]
+fn miner_with_the_ca_and_a_client_certificate_boots() {
+    let server_url = serve();
+    let response = boot(&server_url, trusted()).unwrap();
+    assert!(response.ok, "{:?}", response.msg);
+}
+
+#[test]
+fn push_connection_is_encrypted_too() {
+    let server_url = serve();
+    let queue_path = std::env::temp_dir().join(format!("hasher-tls-push-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&queue_path);
+    let mut application = Application::start(
+        String::from("12345678"),
+        1,
+        String::from("tls-check"),
+        server_url,
+        &settings(trusted()),
+        SubmissionQueue::open(&queue_path).unwrap(),
+        BackendKind::Scalar,
+    ).unwrap();
+    application.push = true;
+    let app = Arc::new(Mutex::new(application));
+    let push_thread = push::begin(App::from(&app));
+    // The pool sends the statistics first thing after the subscription.
+    let started = Instant::now();
+    while !app.lock().map(|app| app.push_connected && app.pool_status.is_some()).unwrap() {
+        assert!(started.elapsed() < Duration::from_secs(20), "the push connection was not opened");
+        thread::sleep(Duration::from_millis(10));
+    }
+    app.lock().unwrap().quitting = true;
+    push_thread.join().unwrap();
+}
+
+fn trusted() -> TlsSettings {
+    TlsSettings {
+        ca_file: Some(certificate("ca.pem")),
+        client_cert: Some(certificate("client.pem")),
+        client_key: Some(certificate("client.key")),
+    }
+}
+
+fn settings(tls: TlsSettings) -> ConnectSettings {
+    ConnectSettings {
+        tls,
+        proxy: Some(String::from(proxy::DIRECT)),
+        ..ConnectSettings::default()
+    }
+}
+
+fn boot(server_url: &str, tls: TlsSettings) -> Result<CommandResponse, NetError> {
+    net::connect(server_url, &settings(tls)).unwrap().boot(&BootRequest {
+        student_number: String::from("12345678"),
+        name: String::from("tls-check"),
+    })
+}
+
+fn certificate(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("tls").join(name)
+}
+
+/// Serve a pool on a free port behind the TLS stand-in. Returns the https://
+/// address of the stand-in. Both run until the test ends.
+fn serve() -> String {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let pool = Arc::new(Pool::new(PoolConfig {
+        bind: format!("127.0.0.1:{}", port),
+        stratum_bind: None,
+        job_size: 1_000,
+        min_zero_bits: 40,
+        workers: 2,
+        job_ttl: None,
+        secrets: None,
+    }));
+    let server = Arc::clone(&pool);
+    thread::spawn(move || pool::http::serve(server).unwrap());
+    let started = Instant::now();
+    while TcpStream::connect(&pool.config().bind).is_err() {
+        assert!(started.elapsed() < Duration::from_secs(20), "the pool did not start");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut roots = RootCertStore::empty();
+    roots.add_pem_file(&mut reader("ca.pem")).unwrap();
+    let mut config = ServerConfig::new(AllowAnyAuthenticatedClient::new(roots));
+    let key = pkcs8_private_keys(&mut reader("pool.key")).unwrap().remove(0);
+    config.set_single_cert(certs(&mut reader("pool.pem")).unwrap(), key).unwrap();
+    let config = Arc::new(config);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server_url = format!("https://localhost:{}", listener.local_addr().unwrap().port());
+    let target = pool.config().bind.clone();
+    thread::spawn(move || {
+        for client in listener.incoming() {
+            let (client, upstream) = match client.and_then(|client| Ok((client, TcpStream::connect(&target)?))) {
+                Ok(pair) => pair,
+                Err(_) => continue,
+            };
+            let session = ServerSession::new(&config);
+            thread::spawn(move || terminate(session, client, upstream));
+        }
+    });
+    server_url
+}
+
+fn reader(name: &str) -> BufReader<File> {
+    BufReader::new(File::open(certificate(name)).unwrap())
+}
+
+/// Decrypt what the miner sends on to the pool and encrypt the answers,
+/// until either side hangs up or the handshake fails.
+fn terminate(mut session: ServerSession, mut client: TcpStream, mut upstream: TcpStream) -> io::Result<()> {
+    client.set_nonblocking(true)?;
+    upstream.set_nonblocking(true)?;
+    let mut buffer = [0; 4096];
+    loop {
+        let mut idle = true;
+        match session.read_tls(&mut client) {
+            Ok(0) => return Ok(()),
+            Ok(_) => {
+                idle = false;
+                if session.process_new_packets().is_err() {
+                    // Send the alert telling the miner why.
+                    let _ = session.write_tls(&mut client);
+                    return Ok(());
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+        match session.read(&mut buffer) {
+            Ok(0) => {}
+            Ok(length) => {
+                idle = false;
+                upstream.write_all(&buffer[..length])?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+        if !session.is_handshaking() {
+            match upstream.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(length) => {
+                    idle = false;
+                    session.write_all(&buffer[..length])?;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        while session.wants_write() {
+            match session.write_tls(&mut client) {
+                Ok(_) => idle = false,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(1)),
+                Err(e) => return Err(e),
+            }
+        }
+        if idle {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}